@@ -3,9 +3,10 @@
 //! 1. Create a trie with some key-value pairs
 //! 2. Generate a Merkle proof for a specific key
 //! 3. Verify the proof against the trie's root hash
-//! 4. Handle various error cases and edge conditions
+//! 4. Prove that a missing key is not in the trie
+//! 5. Handle various error cases and edge conditions
 
-use patricia_merkle_trie::proof::MerkleProofTrait;
+use patricia_merkle_trie::proof::{ExtendedProofTrait, MerkleProofTrait};
 use patricia_merkle_trie::TrieError;
 use patricia_merkle_trie::{PatriciaMerkleTrie, Result};
 
@@ -47,15 +48,16 @@ fn main() -> Result<()> {
                     "  Value: {:?} (expected: {:?})",
                     proof.value, expected_value
                 );
-                println!("  Proof steps:");
-                for (i, (nibble, hash)) in proof.proof.iter().enumerate() {
-                    println!("    {}: nibble={}, hash={:?}", i, nibble, hash);
-
-                    // Show what this hash represents
-                    if *nibble == 0 {
-                        println!("       (branch node hash)");
-                    } else {
-                        println!("       (leaf node hash)");
+                println!("  Proof nodes:");
+                for (i, node) in proof.nodes.iter().enumerate() {
+                    println!("    {}: {:?}", i, node);
+
+                    // Show what this node is, from its tag byte
+                    match node.first() {
+                        Some(0x00) => println!("       (leaf node)"),
+                        Some(0x01) => println!("       (branch node)"),
+                        Some(0x03) => println!("       (extension node)"),
+                        _ => println!("       (empty node)"),
                     }
                 }
 
@@ -90,14 +92,27 @@ fn main() -> Result<()> {
         }
     }
 
-    // 2. Try an empty key
+    // The missing key can instead be proven absent
+    let exclusion_proof = trie.generate_exclusion_proof(&missing_key)?;
+    println!(
+        "Exclusion proof with {} node(s)",
+        exclusion_proof.nodes.len()
+    );
+    let is_absent = PatriciaMerkleTrie::<Vec<u8>, Vec<u8>>::verify_exclusion_proof(
+        root_hash.clone(),
+        exclusion_proof,
+    )?;
+    println!("Exclusion proof verification: {}", is_absent);
+    assert!(is_absent);
+
+    // 2. Try the empty key, which is valid but has no value stored
     let empty_key = Vec::new();
     println!("\nTesting empty key");
     match trie.generate_proof(&empty_key) {
         Ok(_) => println!("Unexpected: proof generated for empty key"),
         Err(e) => {
             println!("Expected error for empty key: {:?}", e);
-            assert!(matches!(e, TrieError::InvalidKey));
+            assert!(matches!(e, TrieError::NodeNotFound));
         }
     }
 
@@ -105,10 +120,10 @@ fn main() -> Result<()> {
     println!("\nTesting invalid proof verification");
     let valid_proof = trie.generate_proof(&keys[0])?;
     let mut invalid_proof = valid_proof.clone();
-    if let Some((_, hash)) = invalid_proof.proof.first_mut() {
-        // Modify the hash to make it invalid
-        if !hash.is_empty() {
-            hash[0] = !hash[0];
+    if let Some(node) = invalid_proof.nodes.first_mut() {
+        // Modify the last byte of the root node to make it invalid
+        if let Some(byte) = node.last_mut() {
+            *byte = !*byte;
         }
     }
 
@@ -0,0 +1,413 @@
+//! Order-preserving key encodings for typed keys.
+//!
+//! The trie stores raw byte keys. The [`KeyCodec`] trait maps typed keys to
+//! bytes such that the byte-wise order of the encodings matches the logical
+//! order of the keys:
+//! - Unsigned integers are encoded big-endian with a fixed width
+//! - Signed integers are encoded big-endian with the sign bit flipped
+//! - Strings and byte strings escape `0x00` as `0x00 0xFF` and end with
+//!   `0x00 0x00`, so a string sorts before every string it is a prefix of
+//! - Tuples concatenate the encodings of their elements
+//!
+//! Every encoding is self-delimiting, which is what allows tuple elements to
+//! be concatenated without losing the ordering. [`TypedTrie`] wraps a
+//! [`PatriciaMerkleTrie`] to accept typed keys directly.
+
+use crate::{
+    error::{Result, TrieError},
+    trie::PatriciaMerkleTrie,
+};
+
+/// Byte introducing an escape sequence or the terminator in string encodings
+const ESCAPE: u8 = 0x00;
+/// Byte following `ESCAPE` for an embedded `0x00`
+const ESCAPED_ZERO: u8 = 0xFF;
+/// Byte following `ESCAPE` at the end of a string
+const TERMINATOR: u8 = 0x00;
+
+/// A key type with an order-preserving byte encoding
+///
+/// For any two keys `a` and `b`, `a < b` if and only if the encoding of `a`
+/// is lexicographically smaller than the encoding of `b`.
+///
+/// The trie accepts keys of at most 32 bytes, and the limit applies to the
+/// encoding. A string takes two bytes more than its length, plus one for
+/// each `0x00` it holds, and a tuple the sum of its elements, so a
+/// `(String, u64)` key leaves room for 22 bytes of string. Longer encodings
+/// can be produced but fail with [`TrieError::KeyTooLong`] when used with a
+/// [`TypedTrie`].
+///
+/// # Examples
+/// ```
+/// # use patricia_merkle_trie::{KeyCodec, Result};
+/// # fn main() -> Result<()> {
+/// let key = ("users".to_string(), 42u32);
+/// let bytes = key.to_key_bytes();
+/// assert_eq!(<(String, u32)>::from_key_bytes(&bytes)?, key);
+/// assert!(5u32.to_key_bytes() < 256u32.to_key_bytes());
+/// # Ok(())
+/// # }
+/// ```
+pub trait KeyCodec: Sized {
+    /// Appends the encoding of the key to `out`
+    fn encode_key(&self, out: &mut Vec<u8>);
+
+    /// Decodes a key from the front of `input`
+    ///
+    /// On success `input` is advanced past the consumed bytes.
+    ///
+    /// # Returns
+    /// * `Ok(Self)` - The decoded key
+    /// * `Err(TrieError::InvalidKey)` - If the input is not a valid encoding
+    fn decode_key(input: &mut &[u8]) -> Result<Self>;
+
+    /// Encodes the key into a new byte vector
+    fn to_key_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_key(&mut out);
+        out
+    }
+
+    /// Decodes a key that must span all of `bytes`
+    ///
+    /// # Returns
+    /// * `Ok(Self)` - The decoded key
+    /// * `Err(TrieError::InvalidKey)` - If the encoding is invalid or has trailing bytes
+    fn from_key_bytes(mut bytes: &[u8]) -> Result<Self> {
+        let key = Self::decode_key(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(TrieError::InvalidKey);
+        }
+        Ok(key)
+    }
+}
+
+/// Splits `len` bytes off the front of `input`
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if input.len() < len {
+        return Err(TrieError::InvalidKey);
+    }
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    Ok(head)
+}
+
+macro_rules! impl_unsigned_codec {
+    ($($ty:ty),*) => {
+        $(
+            impl KeyCodec for $ty {
+                fn encode_key(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_be_bytes());
+                }
+
+                fn decode_key(input: &mut &[u8]) -> Result<Self> {
+                    let bytes = take(input, std::mem::size_of::<$ty>())?;
+                    let mut buf = [0u8; std::mem::size_of::<$ty>()];
+                    buf.copy_from_slice(bytes);
+                    Ok(<$ty>::from_be_bytes(buf))
+                }
+            }
+        )*
+    };
+}
+
+impl_unsigned_codec!(u8, u16, u32, u64, u128);
+
+macro_rules! impl_signed_codec {
+    ($($ty:ty => $unsigned:ty),*) => {
+        $(
+            impl KeyCodec for $ty {
+                fn encode_key(&self, out: &mut Vec<u8>) {
+                    // Flipping the sign bit moves negative numbers below positive ones
+                    let flipped = (*self as $unsigned) ^ (1 << (<$unsigned>::BITS - 1));
+                    flipped.encode_key(out);
+                }
+
+                fn decode_key(input: &mut &[u8]) -> Result<Self> {
+                    let flipped = <$unsigned>::decode_key(input)?;
+                    Ok((flipped ^ (1 << (<$unsigned>::BITS - 1))) as $ty)
+                }
+            }
+        )*
+    };
+}
+
+impl_signed_codec!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+/// Appends the escaped and terminated encoding of a byte string to `out`
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    for &byte in bytes {
+        out.push(byte);
+        if byte == ESCAPE {
+            out.push(ESCAPED_ZERO);
+        }
+    }
+    out.extend_from_slice(&[ESCAPE, TERMINATOR]);
+}
+
+impl KeyCodec for Vec<u8> {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        encode_bytes(self, out);
+    }
+
+    fn decode_key(input: &mut &[u8]) -> Result<Self> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = take(input, 1)?[0];
+            if byte != ESCAPE {
+                bytes.push(byte);
+                continue;
+            }
+            match take(input, 1)?[0] {
+                TERMINATOR => return Ok(bytes),
+                ESCAPED_ZERO => bytes.push(ESCAPE),
+                _ => return Err(TrieError::InvalidKey),
+            }
+        }
+    }
+}
+
+impl KeyCodec for String {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        encode_bytes(self.as_bytes(), out);
+    }
+
+    fn decode_key(input: &mut &[u8]) -> Result<Self> {
+        String::from_utf8(Vec::<u8>::decode_key(input)?).map_err(|_| TrieError::InvalidKey)
+    }
+}
+
+macro_rules! impl_tuple_codec {
+    ($($name:ident),+) => {
+        impl<$($name: KeyCodec),+> KeyCodec for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode_key(out);)+
+            }
+
+            fn decode_key(input: &mut &[u8]) -> Result<Self> {
+                Ok(($($name::decode_key(input)?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple_codec!(A, B);
+impl_tuple_codec!(A, B, C);
+impl_tuple_codec!(A, B, C, D);
+
+/// A Patricia Merkle Trie keyed by typed keys
+///
+/// Keys are stored using their [`KeyCodec`] encoding, so iteration yields
+/// them in their logical order. The encoding must fit in the 32 bytes the
+/// trie allows for a key; [`insert`](Self::insert), [`get`](Self::get) and
+/// [`delete`](Self::delete) fail with [`TrieError::KeyTooLong`] for keys
+/// whose encoding is longer.
+///
+/// # Examples
+/// ```
+/// # use patricia_merkle_trie::{Result, TypedTrie};
+/// # fn main() -> Result<()> {
+/// let mut trie = TypedTrie::new();
+/// trie.insert(("blocks".to_string(), 7u64), b"seven".to_vec())?;
+/// assert_eq!(trie.get(&("blocks".to_string(), 7u64))?.unwrap(), b"seven");
+/// # Ok(())
+/// # }
+/// ```
+pub struct TypedTrie<K, V> {
+    trie: PatriciaMerkleTrie<Vec<u8>, V>,
+    _key: std::marker::PhantomData<K>,
+}
+
+impl<K, V> TypedTrie<K, V>
+where
+    K: KeyCodec,
+    V: Clone + AsRef<[u8]> + std::fmt::Debug,
+{
+    /// Creates a new empty typed trie
+    pub fn new() -> Self {
+        TypedTrie {
+            trie: PatriciaMerkleTrie::new(),
+            _key: std::marker::PhantomData,
+        }
+    }
+
+    /// Inserts a key-value pair into the trie
+    ///
+    /// # Returns
+    /// * `Ok(())` on successful insertion
+    /// * `Err(TrieError::KeyTooLong)` if the encoded key is longer than 32
+    ///   bytes
+    pub fn insert(&mut self, key: K, value: V) -> Result<()> {
+        self.trie.insert(key.to_key_bytes(), value)
+    }
+
+    /// Retrieves a value by key from the trie
+    ///
+    /// # Returns
+    /// * `Ok(Some(&V))` if the key exists
+    /// * `Ok(None)` if the key doesn't exist
+    pub fn get(&self, key: &K) -> Result<Option<&V>> {
+        self.trie.get(&key.to_key_bytes())
+    }
+
+    /// Deletes a key-value pair from the trie
+    ///
+    /// # Returns
+    /// * `Ok(Some(V))` if the key was found and deleted
+    /// * `Ok(None)` if the key didn't exist
+    pub fn delete(&mut self, key: &K) -> Result<Option<V>> {
+        self.trie.delete(&key.to_key_bytes())
+    }
+
+    /// Computes the cryptographic hash of the entire trie
+    pub fn root_hash(&self) -> Result<Vec<u8>> {
+        self.trie.root_hash()
+    }
+
+    /// Returns an iterator over all entries in key order
+    ///
+    /// Each item fails with `TrieError::InvalidKey` if a stored key cannot be
+    /// decoded as `K`.
+    pub fn iter(&self) -> impl Iterator<Item = Result<(K, &V)>> + '_ {
+        self.trie
+            .iter()
+            .map(|(key, value)| Ok((K::from_key_bytes(&key)?, value)))
+    }
+
+    /// Returns the underlying trie keyed by encoded keys
+    pub fn trie(&self) -> &PatriciaMerkleTrie<Vec<u8>, V> {
+        &self.trie
+    }
+}
+
+impl<K, V> Default for TypedTrie<K, V>
+where
+    K: KeyCodec,
+    V: Clone + AsRef<[u8]> + std::fmt::Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_order_preserved<K: KeyCodec + Ord + std::fmt::Debug + Clone>(keys: &[K]) {
+        let mut sorted = keys.to_vec();
+        sorted.sort();
+        let mut by_bytes = keys.to_vec();
+        by_bytes.sort_by_key(|k| k.to_key_bytes());
+        assert_eq!(sorted, by_bytes);
+        for key in keys {
+            assert_eq!(K::from_key_bytes(&key.to_key_bytes()).unwrap(), *key);
+        }
+    }
+
+    #[test]
+    fn test_integer_order() {
+        assert_order_preserved(&[0u16, 1, 255, 256, u16::MAX]);
+        assert_order_preserved(&[0u128, 1, u64::MAX as u128, u128::MAX]);
+        assert_order_preserved(&[i64::MIN, -256, -1, 0, 1, 255, i64::MAX]);
+        assert_eq!(258u32.to_key_bytes(), vec![0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_string_order() {
+        assert_order_preserved(&[
+            String::new(),
+            "a".to_string(),
+            "a\0".to_string(),
+            "a\0b".to_string(),
+            "ab".to_string(),
+            "b".to_string(),
+        ]);
+        assert_eq!("a\0".to_string().to_key_bytes(), vec![b'a', 0, 0xFF, 0, 0]);
+        assert_eq!(
+            "a\0b".to_string().to_key_bytes(),
+            b"a\0b".to_vec().to_key_bytes()
+        );
+    }
+
+    #[test]
+    fn test_tuple_order() {
+        assert_order_preserved(&[
+            ("a".to_string(), 2u8),
+            ("a".to_string(), 10u8),
+            ("ab".to_string(), 0u8),
+            ("b".to_string(), 1u8),
+        ]);
+        assert_order_preserved(&[(1u8, -1i64, vec![0u8]), (1, -1, vec![]), (0, 5, vec![9])]);
+    }
+
+    #[test]
+    fn test_invalid_encodings() {
+        assert!(matches!(
+            u32::from_key_bytes(&[1, 2]),
+            Err(TrieError::InvalidKey)
+        ));
+        assert!(matches!(
+            u8::from_key_bytes(&[1, 2]),
+            Err(TrieError::InvalidKey)
+        ));
+        assert!(matches!(
+            Vec::<u8>::from_key_bytes(&[1, 0, 7]),
+            Err(TrieError::InvalidKey)
+        ));
+        assert!(matches!(
+            String::from_key_bytes(&[0xC3, 0, 0]),
+            Err(TrieError::InvalidKey)
+        ));
+    }
+
+    #[test]
+    fn test_typed_trie() -> Result<()> {
+        let mut trie = TypedTrie::new();
+        for i in [300u32, 2, 70000, 1] {
+            trie.insert(i, i.to_be_bytes().to_vec())?;
+        }
+        assert_eq!(trie.get(&70000)?.unwrap(), &70000u32.to_be_bytes());
+
+        let keys = trie
+            .iter()
+            .map(|entry| entry.map(|(key, _)| key))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(keys, vec![1, 2, 300, 70000]);
+
+        assert!(trie.delete(&2)?.is_some());
+        assert!(trie.get(&2)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_trie_key_length_limit() -> Result<()> {
+        // 30 bytes of string and its terminator fill the 32 bytes exactly
+        let mut strings = TypedTrie::new();
+        strings.insert("a".repeat(30), vec![1])?;
+        assert!(matches!(
+            strings.insert("a".repeat(31), vec![2]),
+            Err(TrieError::KeyTooLong)
+        ));
+        assert!(matches!(
+            strings.get(&"a".repeat(31)),
+            Err(TrieError::KeyTooLong)
+        ));
+
+        // Four u64 elements take 32 bytes, widening one to u128 takes 40
+        let mut tuples = TypedTrie::new();
+        tuples.insert((1u64, 2u64, 3u64, 4u64), vec![1])?;
+        let mut wide = TypedTrie::new();
+        assert!(matches!(
+            wide.insert((1u64, 2u64, 3u64, 4u128), vec![1]),
+            Err(TrieError::KeyTooLong)
+        ));
+        assert!(matches!(
+            wide.delete(&(1u64, 2u64, 3u64, 4u128)),
+            Err(TrieError::KeyTooLong)
+        ));
+        Ok(())
+    }
+}
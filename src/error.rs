@@ -4,6 +4,10 @@
 //! during trie operations, providing specific error types for different
 //! failure scenarios.
 
+use crate::{
+    trie::HashMode,
+    verify::{ProofError, VerifyError},
+};
 use thiserror::Error;
 
 /// Errors that can occur during Patricia Merkle Trie operations
@@ -12,7 +16,7 @@ use thiserror::Error;
 /// during trie operations such as insertion, deletion, or traversal.
 #[derive(Error, Debug)]
 pub enum TrieError {
-    /// Indicates that a key has an invalid format or is empty
+    /// Indicates that a key has an invalid format
     #[error("Invalid key format")]
     InvalidKey,
 
@@ -44,9 +48,45 @@ pub enum TrieError {
     #[error("Corrupted branch structure")]
     CorruptedBranch,
 
+    /// Indicates that a key is present where its absence was required
+    #[error("Key exists")]
+    KeyExists,
+
     /// Indicates that a proof is invalid
     #[error("Invalid proof")]
     InvalidProof,
+
+    /// Indicates that RLP data is malformed or not in canonical form
+    #[error("Invalid RLP encoding")]
+    InvalidRlp,
+
+    /// Indicates that an operation is not available for the hash mode of
+    /// the trie
+    #[error("Unsupported in {0:?} hash mode")]
+    UnsupportedHashMode(HashMode),
+
+    /// Indicates that an operation reached a subtree that was not revealed,
+    /// holding the path to that subtree
+    #[error("Missing witness for path {0:?}")]
+    MissingWitness(Vec<u8>),
+
+    /// Indicates the step at which a proof failed to verify
+    #[error("Proof check failed: {0}")]
+    ProofFailed(ProofError),
+}
+
+impl From<VerifyError> for TrieError {
+    fn from(err: VerifyError) -> Self {
+        match err {
+            VerifyError::InvalidProof => TrieError::InvalidProof,
+        }
+    }
+}
+
+impl From<ProofError> for TrieError {
+    fn from(err: ProofError) -> Self {
+        TrieError::ProofFailed(err)
+    }
 }
 
 /// Type alias for Result with TrieError as the error type
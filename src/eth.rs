@@ -0,0 +1,546 @@
+//! Ethereum-compatible root hash computation.
+//!
+//! Computes the root of the Merkle Patricia Trie defined in the Ethereum
+//! yellow paper over a set of key-value pairs, so roots can be checked
+//! against state, transaction and receipt roots of real blocks:
+//!
+//! - Keys are split into 4-bit nibbles, high nibble first
+//! - Leaf and extension paths are hex-prefix encoded
+//! - Nodes are RLP encoded: a leaf or extension is `[path, value_or_child]`
+//!   and a branch is a list of 16 children followed by its value
+//! - A child whose encoding is shorter than 32 bytes is embedded in its
+//!   parent, any other child is referenced by the Keccak-256 hash of its
+//!   encoding
+//! - The root is always the Keccak-256 hash of its encoding, and the root
+//!   of the empty trie is `keccak256(rlp(""))`
+//!
+//! Ethereum treats an empty value as a deletion, so entries with empty
+//! values do not contribute to the root.
+
+use crate::{
+    error::{Result, TrieError},
+    rlp::{decode, encode_bytes, encode_list, RlpItem},
+    trie::{HashMode, PatriciaMerkleTrie},
+    utils::to_hex_nibbles,
+};
+
+pub use crate::utils::{hex_prefix_decode, hex_prefix_encode};
+use sha3::{Digest, Keccak256};
+use std::collections::HashMap;
+
+/// Root hash of the empty trie, `keccak256(rlp(""))`
+pub const EMPTY_ROOT: [u8; 32] = [
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+];
+
+/// Length from which encoded nodes are referenced by hash instead of embedded
+const HASH_LEN: usize = 32;
+
+/// Computes the Keccak-256 hash of arbitrary data
+pub fn keccak256(data: &[u8]) -> Vec<u8> {
+    Keccak256::digest(data).to_vec()
+}
+
+/// Sorted entries with keys split into nibbles, without empty values
+type NibbleEntries<'a> = Vec<(Vec<u8>, &'a [u8])>;
+
+/// Splits the keys of `entries` into nibbles, dropping empty values
+fn nibble_entries<'a, I>(entries: I) -> NibbleEntries<'a>
+where
+    I: IntoIterator<Item = (Vec<u8>, &'a [u8])>,
+{
+    entries
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| (to_hex_nibbles(&key), value))
+        .collect()
+}
+
+/// Computes the Ethereum root hash of a set of entries
+///
+/// # Arguments
+/// * `entries` - Key-value pairs sorted by key, without duplicate keys
+///
+/// # Returns
+/// The 32-byte Keccak-256 root hash
+pub(crate) fn root_hash<'a, I>(entries: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = (Vec<u8>, &'a [u8])>,
+{
+    EthTrie::new(entries).root
+}
+
+/// Computes the root of a trie of items keyed by their RLP encoded index
+///
+/// This is how block headers commit to their transactions, receipts and
+/// withdrawals: the `i`-th item is stored under `rlp(i)`.
+///
+/// # Arguments
+/// * `items` - Encoded items in order, such as signed transactions or receipts
+///
+/// # Returns
+/// The 32-byte Keccak-256 root hash
+///
+/// # Examples
+/// ```
+/// # use patricia_merkle_trie::eth::{ordered_trie_root, EMPTY_ROOT};
+/// assert_eq!(ordered_trie_root(Vec::<Vec<u8>>::new()), EMPTY_ROOT.to_vec());
+/// let root = ordered_trie_root([b"first".to_vec(), b"second".to_vec()]);
+/// assert_eq!(root.len(), 32);
+/// ```
+pub fn ordered_trie_root<I, T>(items: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let items: Vec<T> = items.into_iter().collect();
+    let mut entries: Vec<(Vec<u8>, &[u8])> = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| (encode_index(index), item.as_ref()))
+        .collect();
+    // Index 0 encodes as 0x80 and sorts after indices 1 to 127
+    entries.sort();
+    root_hash(entries)
+}
+
+/// RLP encodes an index as an integer without leading zeros
+fn encode_index(index: usize) -> Vec<u8> {
+    let bytes = index.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    encode_bytes(&bytes[skip..])
+}
+
+/// An Ethereum trie encoded once, from which proofs are read
+///
+/// Holds the encoding of every node referenced by hash, so that proofs
+/// for any number of keys share the work of encoding the trie.
+#[derive(Debug, Clone)]
+pub(crate) struct EthTrie {
+    root: Vec<u8>,
+    nodes: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl EthTrie {
+    /// Encodes the trie holding `entries`
+    ///
+    /// # Arguments
+    /// * `entries` - Key-value pairs sorted by key, without duplicate keys
+    pub(crate) fn new<'a, I>(entries: I) -> Self
+    where
+        I: IntoIterator<Item = (Vec<u8>, &'a [u8])>,
+    {
+        let entries = nibble_entries(entries);
+        let mut nodes = HashMap::new();
+        if entries.is_empty() {
+            return EthTrie {
+                root: EMPTY_ROOT.to_vec(),
+                nodes,
+            };
+        }
+        // The root is referenced by hash even when it is short
+        let encoded = encode_node(&entries, 0, &mut nodes);
+        let root = keccak256(&encoded);
+        nodes.insert(root.clone(), encoded);
+        EthTrie { root, nodes }
+    }
+
+    /// Returns the 32-byte Keccak-256 root hash
+    pub(crate) fn root_hash(&self) -> &[u8] {
+        &self.root
+    }
+
+    /// Generates a proof for `key`
+    ///
+    /// The proof lists the RLP encodings of the nodes on the path to `key`,
+    /// starting at the root, leaving out nodes embedded in their parent. It
+    /// proves the value of `key`, or its absence if the path ends before
+    /// reaching it.
+    ///
+    /// # Returns
+    /// The encoded proof nodes, empty for the empty trie
+    pub(crate) fn prove(&self, key: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let nibbles = to_hex_nibbles(key);
+        let mut proof = Vec::new();
+        let mut pos = 0;
+        let mut next = ChildRef::Hash(self.root.clone());
+
+        loop {
+            let node = match next {
+                ChildRef::Empty => break,
+                ChildRef::Hash(hash) => match self.nodes.get(&hash) {
+                    Some(encoded) => {
+                        proof.push(encoded.clone());
+                        decode(encoded)?
+                    }
+                    // Only the root of the empty trie has no encoding
+                    None => break,
+                },
+                ChildRef::Inline(item) => item,
+            };
+
+            let items = node.as_list().ok_or(TrieError::InvalidNodeType)?;
+            next = if items.len() == 17 {
+                let Some(&nibble) = nibbles.get(pos) else {
+                    break;
+                };
+                pos += 1;
+                ChildRef::from_item(&items[nibble as usize])?
+            } else {
+                let path = items[0].as_bytes().ok_or(TrieError::InvalidNodeType)?;
+                let (path, is_leaf) = hex_prefix_decode(path)?;
+                if is_leaf || !nibbles[pos..].starts_with(&path) {
+                    break;
+                }
+                pos += path.len();
+                ChildRef::from_item(&items[1])?
+            };
+        }
+        Ok(proof)
+    }
+}
+
+/// RLP encodes the node holding `entries`, whose keys share their first `depth` nibbles
+///
+/// Descendants referenced by hash are recorded in `nodes` under that hash.
+fn encode_node(
+    entries: &[(Vec<u8>, &[u8])],
+    depth: usize,
+    nodes: &mut HashMap<Vec<u8>, Vec<u8>>,
+) -> Vec<u8> {
+    if let [(key, value)] = entries {
+        return encode_list(&[
+            encode_bytes(&hex_prefix_encode(&key[depth..], true)),
+            encode_bytes(value),
+        ]);
+    }
+
+    // Entries are sorted, so the first and last keys bound the shared prefix
+    let first = &entries[0].0[depth..];
+    let last = &entries[entries.len() - 1].0[depth..];
+    let shared = first.iter().zip(last).take_while(|(a, b)| a == b).count();
+    if shared > 0 {
+        let child = encode_node(entries, depth + shared, nodes);
+        return encode_list(&[
+            encode_bytes(&hex_prefix_encode(&first[..shared], false)),
+            child_reference(child, nodes),
+        ]);
+    }
+
+    let mut items = Vec::with_capacity(17);
+    let mut value = encode_bytes(&[]);
+    let mut rest = entries;
+    // A key ending here sorts first and becomes the branch value
+    if rest[0].0.len() == depth {
+        value = encode_bytes(rest[0].1);
+        rest = &rest[1..];
+    }
+    for nibble in 0..16u8 {
+        let count = rest
+            .iter()
+            .take_while(|(key, _)| key[depth] == nibble)
+            .count();
+        if count == 0 {
+            items.push(encode_bytes(&[]));
+        } else {
+            let child = encode_node(&rest[..count], depth + 1, nodes);
+            items.push(child_reference(child, nodes));
+            rest = &rest[count..];
+        }
+    }
+    items.push(value);
+    encode_list(&items)
+}
+
+/// Embeds a short node encoding, or references a longer one by its hash
+fn child_reference(encoded: Vec<u8>, nodes: &mut HashMap<Vec<u8>, Vec<u8>>) -> Vec<u8> {
+    if encoded.len() < HASH_LEN {
+        encoded
+    } else {
+        let hash = keccak256(&encoded);
+        let reference = encode_bytes(&hash);
+        nodes.insert(hash, encoded);
+        reference
+    }
+}
+
+impl<K, V> PatriciaMerkleTrie<K, V>
+where
+    K: AsRef<[u8]> + Clone + From<Vec<u8>> + std::fmt::Debug,
+    V: Clone + AsRef<[u8]> + std::fmt::Debug,
+{
+    /// Generates a proof for `key` against the Ethereum root of the trie
+    ///
+    /// The proof lists the RLP encoded nodes on the path to `key`, as
+    /// `eth_getProof` does, and is checked with [`verify_proof`]. It proves
+    /// the value of `key`, or its absence.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<Vec<u8>>)` - The encoded proof nodes, root first
+    /// * `Err(TrieError::UnsupportedHashMode)` - If the trie is not in
+    ///   [`HashMode::Ethereum`]
+    pub fn generate_eth_proof(&self, key: &K) -> Result<Vec<Vec<u8>>> {
+        self.require_mode(HashMode::Ethereum)?;
+        self.eth_trie().prove(key.as_ref())
+    }
+}
+
+/// Reference from a node to one of its children
+enum ChildRef {
+    /// No child
+    Empty,
+    /// Child referenced by the hash of its encoding
+    Hash(Vec<u8>),
+    /// Child embedded in its parent
+    Inline(RlpItem),
+}
+
+impl ChildRef {
+    /// Interprets an item of a decoded node as a child reference
+    fn from_item(item: &RlpItem) -> Result<Self> {
+        match item {
+            RlpItem::Bytes(bytes) if bytes.is_empty() => Ok(ChildRef::Empty),
+            RlpItem::Bytes(bytes) if bytes.len() == HASH_LEN => Ok(ChildRef::Hash(bytes.clone())),
+            RlpItem::List(_) => Ok(ChildRef::Inline(item.clone())),
+            RlpItem::Bytes(_) => Err(TrieError::InvalidProof),
+        }
+    }
+}
+
+/// Verifies a proof for `key` against an Ethereum root hash
+///
+/// # Arguments
+/// * `root` - Root hash of the trie
+/// * `key` - Key the proof is for
+/// * `proof` - RLP encoded nodes on the path to `key`, starting at the root
+///
+/// # Returns
+/// * `Ok(Some(value))` - If the proof shows `key` holds `value`
+/// * `Ok(None)` - If the proof shows `key` is absent
+/// * `Err(TrieError)` - If the proof is malformed, does not match `root`,
+///   or contains nodes that are not on the path
+pub fn verify_proof(root: &[u8], key: &[u8], proof: &[Vec<u8>]) -> Result<Option<Vec<u8>>> {
+    let nibbles = to_hex_nibbles(key);
+    let mut nodes = proof.iter();
+    let mut pos = 0;
+    let mut next = ChildRef::Hash(root.to_vec());
+
+    let value = loop {
+        let node = match next {
+            ChildRef::Empty => break None,
+            ChildRef::Hash(hash) => match nodes.next() {
+                Some(encoded) if keccak256(encoded) == hash => decode(encoded)?,
+                // The empty trie needs no proof nodes
+                None if hash == EMPTY_ROOT && pos == 0 => break None,
+                _ => return Err(TrieError::InvalidProof),
+            },
+            ChildRef::Inline(item) => item,
+        };
+
+        match node {
+            RlpItem::Bytes(bytes) if bytes.is_empty() => break None,
+            RlpItem::List(items) if items.len() == 17 => {
+                let Some(&nibble) = nibbles.get(pos) else {
+                    let value = items[16].as_bytes().ok_or(TrieError::InvalidProof)?;
+                    break (!value.is_empty()).then(|| value.to_vec());
+                };
+                next = ChildRef::from_item(&items[nibble as usize])?;
+                pos += 1;
+            }
+            RlpItem::List(items) if items.len() == 2 => {
+                let path = items[0].as_bytes().ok_or(TrieError::InvalidProof)?;
+                let (path, is_leaf) = hex_prefix_decode(path)?;
+                let rest = &nibbles[pos..];
+                if is_leaf {
+                    if rest != path.as_slice() {
+                        break None;
+                    }
+                    let value = items[1].as_bytes().ok_or(TrieError::InvalidProof)?;
+                    break Some(value.to_vec());
+                }
+                if path.is_empty() {
+                    return Err(TrieError::InvalidProof);
+                }
+                if !rest.starts_with(&path) {
+                    break None;
+                }
+                next = ChildRef::from_item(&items[1])?;
+                pos += path.len();
+            }
+            _ => return Err(TrieError::InvalidProof),
+        }
+    };
+
+    // Every proof node must have been used to reach the result
+    if nodes.next().is_some() {
+        return Err(TrieError::InvalidProof);
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::{HashMode, PatriciaMerkleTrie};
+
+    #[test]
+    fn test_empty_root() {
+        assert_eq!(keccak256(&encode_bytes(&[])), EMPTY_ROOT.to_vec());
+        assert_eq!(root_hash(Vec::new()), EMPTY_ROOT.to_vec());
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn sorted_entries(keys: &[&'static [u8]]) -> Vec<(Vec<u8>, &'static [u8])> {
+        let mut entries: Vec<_> = keys.iter().map(|k| (k.to_vec(), *k)).collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn test_proof_round_trip() -> Result<()> {
+        let keys: &[&[u8]] = &[
+            b"do",
+            b"dog",
+            b"doge",
+            b"horse",
+            b"a much longer key that is never inlined",
+            b"a much longer key that is never inlined, twice",
+        ];
+        let entries = sorted_entries(keys);
+        let trie = EthTrie::new(entries);
+        let root = trie.root_hash();
+
+        for key in keys {
+            let proof = trie.prove(key)?;
+            assert_eq!(verify_proof(root, key, &proof)?, Some(key.to_vec()));
+        }
+        for key in [&b"d"[..], b"dot", b"doges", b"horses", b"zebra", b""] {
+            let proof = trie.prove(key)?;
+            assert_eq!(verify_proof(root, key, &proof)?, None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_rejects_tampering() -> Result<()> {
+        let trie = EthTrie::new(sorted_entries(&[b"dog", b"doge", b"horse"]));
+        let root = trie.root_hash();
+        let proof = trie.prove(b"dog")?;
+
+        // Wrong root
+        assert!(verify_proof(&EMPTY_ROOT, b"dog", &proof).is_err());
+        // Modified node
+        let mut modified = proof.clone();
+        let last = modified.last_mut().unwrap();
+        *last.last_mut().unwrap() ^= 1;
+        assert!(verify_proof(root, b"dog", &modified).is_err());
+        // Missing and extra nodes
+        assert!(verify_proof(root, b"dog", &proof[..proof.len() - 1]).is_err());
+        let mut extra = proof.clone();
+        extra.push(proof[0].clone());
+        assert!(verify_proof(root, b"dog", &extra).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_trie_proof() -> Result<()> {
+        let proof = EthTrie::new(Vec::new()).prove(b"dog")?;
+        assert!(proof.is_empty());
+        assert_eq!(verify_proof(&EMPTY_ROOT, b"dog", &proof)?, None);
+        assert_eq!(verify_proof(&EMPTY_ROOT, b"dog", &[vec![0x80]])?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trie_proof_round_trip() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::with_mode(HashMode::Ethereum);
+        for key in [&b"do"[..], b"dog", b"doge", b"horse"] {
+            trie.insert(key.to_vec(), [b"value of ", key].concat())?;
+        }
+        let root = trie.root_hash()?;
+
+        let proof = trie.generate_eth_proof(&b"dog".to_vec())?;
+        assert_eq!(
+            verify_proof(&root, b"dog", &proof)?,
+            Some(b"value of dog".to_vec())
+        );
+        let proof = trie.generate_eth_proof(&b"dot".to_vec())?;
+        assert_eq!(verify_proof(&root, b"dot", &proof)?, None);
+
+        let native: PatriciaMerkleTrie<Vec<u8>, Vec<u8>> = PatriciaMerkleTrie::new();
+        assert!(matches!(
+            native.generate_eth_proof(&b"dog".to_vec()),
+            Err(TrieError::UnsupportedHashMode(HashMode::Native))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_trie_root_follows_changes() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::with_mode(HashMode::Ethereum);
+        trie.insert(b"doe".to_vec(), b"reindeer".to_vec())?;
+        let first = trie.root_hash()?;
+        assert_eq!(trie.root_hash()?, first);
+
+        trie.insert(b"dog".to_vec(), b"puppy".to_vec())?;
+        let second = trie.root_hash()?;
+        assert_ne!(second, first);
+        assert_eq!(
+            second,
+            root_hash(vec![
+                (b"doe".to_vec(), &b"reindeer"[..]),
+                (b"dog".to_vec(), &b"puppy"[..]),
+            ])
+        );
+
+        trie.delete(&b"dog".to_vec())?;
+        assert_eq!(trie.root_hash()?, first);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_index() {
+        assert_eq!(encode_index(0), vec![0x80]);
+        assert_eq!(encode_index(1), vec![0x01]);
+        assert_eq!(encode_index(127), vec![0x7f]);
+        assert_eq!(encode_index(128), vec![0x81, 0x80]);
+        assert_eq!(encode_index(256), vec![0x82, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn test_ordered_trie_root() {
+        // transactionsRoot and receiptsRoot of a block without transactions,
+        // such as mainnet block 0
+        assert_eq!(
+            ordered_trie_root(Vec::<Vec<u8>>::new()),
+            hex("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+        );
+
+        // One item is a leaf under rlp(0) = 0x80, nibbles 8 0, whose path
+        // is hex-prefix encoded as 0x20 0x80
+        let leaf = [0xc4, 0x82, 0x20, 0x80, b'a'];
+        assert_eq!(ordered_trie_root([b"a"]), keccak256(&leaf));
+
+        // Two items branch on the first nibble of rlp(1) = 0x01 and
+        // rlp(0) = 0x80, each leaf keeping one nibble of path
+        let mut branch = vec![0xd5, 0xc2, 0x31, b'b'];
+        branch.extend([0x80; 7]);
+        branch.extend([0xc2, 0x30, b'a']);
+        branch.extend([0x80; 8]);
+        assert_eq!(ordered_trie_root([b"a", b"b"]), keccak256(&branch));
+    }
+
+    #[test]
+    fn test_empty_values_are_ignored() {
+        let with_empty = root_hash(vec![(b"a".to_vec(), &b"1"[..]), (b"b".to_vec(), &[][..])]);
+        let without = root_hash(vec![(b"a".to_vec(), &b"1"[..])]);
+        assert_eq!(with_empty, without);
+    }
+}
@@ -0,0 +1,511 @@
+//! Account and storage proofs in the shape of `eth_getProof`.
+//!
+//! Builds on the Ethereum root mode to model the Ethereum state: a trie of
+//! accounts keyed by the Keccak-256 hash of their address, where each
+//! account holds the root of its own storage trie, keyed by the Keccak-256
+//! hash of the 32-byte slot. [`EthState::get_proof`] returns an [`EthProof`]
+//! with the fields of the `eth_getProof` JSON-RPC response, and
+//! [`EthProof::verify`] checks such a proof, including one captured from an
+//! Ethereum node, against a state root.
+//!
+//! An [`EthProof`] is exchanged in the JSON form of `eth_getProof`, which
+//! Ethereum nodes and clients already read and write. It has no encoding in
+//! the binary format of [`crate::proof`], whose version byte covers only
+//! the native proofs and witnesses.
+//!
+//! [`EthState::get_proof`]: crate::eth_proof::EthState::get_proof
+//! [`EthProof`]: crate::eth_proof::EthProof
+//! [`EthProof::verify`]: crate::eth_proof::EthProof::verify
+
+use crate::{
+    error::{Result, TrieError},
+    eth::{self, keccak256, EthTrie, EMPTY_ROOT},
+    rlp::{decode, encode_bytes, encode_list, RlpItem},
+    trie::{HashMode, PatriciaMerkleTrie},
+};
+use std::{collections::BTreeMap, sync::OnceLock};
+
+/// Code hash of an account without code, `keccak256("")`
+pub const EMPTY_CODE_HASH: [u8; 32] = [
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+    0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+];
+
+/// An Ethereum account as stored in the state trie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    /// Number of transactions sent from the account
+    pub nonce: u64,
+    /// Balance in wei, a big-endian 256-bit integer
+    pub balance: [u8; 32],
+    /// Root hash of the account's storage trie
+    pub storage_hash: [u8; 32],
+    /// Hash of the account's code
+    pub code_hash: [u8; 32],
+}
+
+impl Account {
+    /// RLP encodes the account as `[nonce, balance, storage_hash, code_hash]`
+    pub fn encode(&self) -> Vec<u8> {
+        encode_list(&[
+            encode_scalar(&self.nonce.to_be_bytes()),
+            encode_scalar(&self.balance),
+            encode_bytes(&self.storage_hash),
+            encode_bytes(&self.code_hash),
+        ])
+    }
+
+    /// Decodes an RLP encoded account
+    ///
+    /// # Returns
+    /// * `Ok(Account)` - The decoded account
+    /// * `Err(TrieError::InvalidRlp)` - If the data is not a valid account encoding
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let item = decode(data)?;
+        let fields = match item.as_list() {
+            Some(fields) if fields.len() == 4 => fields,
+            _ => return Err(TrieError::InvalidRlp),
+        };
+        Ok(Account {
+            nonce: u64::from_be_bytes(decode_scalar(&fields[0])?),
+            balance: decode_scalar(&fields[1])?,
+            storage_hash: decode_hash(&fields[2])?,
+            code_hash: decode_hash(&fields[3])?,
+        })
+    }
+}
+
+/// RLP encodes a big-endian integer without leading zeros
+fn encode_scalar(bytes: &[u8]) -> Vec<u8> {
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    encode_bytes(&bytes[skip..])
+}
+
+/// Decodes an RLP encoded integer into a big-endian array of `N` bytes
+fn decode_scalar<const N: usize>(item: &RlpItem) -> Result<[u8; N]> {
+    let bytes = item.as_bytes().ok_or(TrieError::InvalidRlp)?;
+    // Integers have no leading zeros and must fit the target type
+    if bytes.first() == Some(&0) || bytes.len() > N {
+        return Err(TrieError::InvalidRlp);
+    }
+    let mut out = [0u8; N];
+    out[N - bytes.len()..].copy_from_slice(bytes);
+    Ok(out)
+}
+
+/// Decodes an RLP encoded 32-byte hash
+fn decode_hash(item: &RlpItem) -> Result<[u8; 32]> {
+    item.as_bytes()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(TrieError::InvalidRlp)
+}
+
+/// Proof of a storage slot of an account, an entry of `storageProof`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageProof {
+    /// The storage slot
+    pub key: [u8; 32],
+    /// Value of the slot, zero if unset
+    pub value: [u8; 32],
+    /// RLP encoded nodes on the path to the slot in the storage trie
+    pub proof: Vec<Vec<u8>>,
+}
+
+/// Proof of an account and some of its storage slots
+///
+/// Mirrors the response of the `eth_getProof` JSON-RPC method, and is
+/// exchanged in that JSON form rather than the binary proof format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EthProof {
+    /// Address of the account
+    pub address: [u8; 20],
+    /// RLP encoded nodes on the path to the account in the state trie
+    pub account_proof: Vec<Vec<u8>>,
+    /// Balance of the account in wei, a big-endian 256-bit integer
+    pub balance: [u8; 32],
+    /// Hash of the account's code
+    pub code_hash: [u8; 32],
+    /// Nonce of the account
+    pub nonce: u64,
+    /// Root hash of the account's storage trie
+    pub storage_hash: [u8; 32],
+    /// Proofs of the requested storage slots
+    pub storage_proof: Vec<StorageProof>,
+}
+
+impl EthProof {
+    /// Verifies the proof against a state root
+    ///
+    /// An account that does not exist is proven by an exclusion proof and
+    /// must be reported with a zero nonce and balance. Its code and storage
+    /// hashes may be either zero or the hashes of empty code and storage,
+    /// as Ethereum clients differ in how they report missing accounts.
+    ///
+    /// # Arguments
+    /// * `state_root` - State root of the block the proof was taken at
+    ///
+    /// # Returns
+    /// * `Ok(true)` - If the proofs match the state root and the reported values
+    /// * `Ok(false)` - If the proofs are valid but the reported values differ
+    /// * `Err(TrieError)` - If a proof is malformed or does not match its root
+    pub fn verify(&self, state_root: &[u8]) -> Result<bool> {
+        let account =
+            eth::verify_proof(state_root, &keccak256(&self.address), &self.account_proof)?;
+        match account {
+            Some(encoded) => {
+                let account = Account::decode(&encoded)?;
+                if account != self.account() {
+                    return Ok(false);
+                }
+            }
+            None => {
+                let empty = self.nonce == 0
+                    && self.balance == [0; 32]
+                    && (self.storage_hash == [0; 32] || self.storage_hash == EMPTY_ROOT)
+                    && (self.code_hash == [0; 32] || self.code_hash == EMPTY_CODE_HASH);
+                if !empty {
+                    return Ok(false);
+                }
+            }
+        }
+
+        let storage_root = if self.storage_hash == [0; 32] {
+            EMPTY_ROOT
+        } else {
+            self.storage_hash
+        };
+        for slot in &self.storage_proof {
+            let value = eth::verify_proof(&storage_root, &keccak256(&slot.key), &slot.proof)?;
+            let value = match value {
+                Some(encoded) => decode_scalar(&decode(&encoded)?)?,
+                None => [0; 32],
+            };
+            if value != slot.value {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns the account reported by the proof
+    pub fn account(&self) -> Account {
+        Account {
+            nonce: self.nonce,
+            balance: self.balance,
+            storage_hash: self.storage_hash,
+            code_hash: self.code_hash,
+        }
+    }
+}
+
+/// An account with its storage trie
+struct AccountEntry {
+    nonce: u64,
+    balance: [u8; 32],
+    code_hash: [u8; 32],
+    storage: PatriciaMerkleTrie<Vec<u8>, Vec<u8>>,
+}
+
+impl AccountEntry {
+    fn account(&self) -> Result<Account> {
+        let mut storage_hash = [0u8; 32];
+        storage_hash.copy_from_slice(&self.storage.root_hash()?);
+        Ok(Account {
+            nonce: self.nonce,
+            balance: self.balance,
+            storage_hash,
+            code_hash: self.code_hash,
+        })
+    }
+}
+
+/// Ethereum world state that produces `eth_getProof` proofs
+///
+/// # Examples
+/// ```
+/// # use patricia_merkle_trie::{eth_proof::EthState, Result};
+/// # fn main() -> Result<()> {
+/// let mut balance = [0; 32];
+/// balance[24..].copy_from_slice(&1_000_000_000u64.to_be_bytes());
+///
+/// let mut state = EthState::new();
+/// state.set_account([0x11; 20], 1, balance, [0; 32]);
+/// state.set_storage([0x11; 20], [0; 32], [0x2a; 32])?;
+///
+/// let proof = state.get_proof([0x11; 20], &[[0; 32]])?;
+/// assert!(proof.verify(&state.state_root()?)?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct EthState {
+    accounts: BTreeMap<[u8; 20], AccountEntry>,
+    /// The state trie, built on first use after each change
+    state: OnceLock<EthTrie>,
+}
+
+impl EthState {
+    /// Creates an empty state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates or updates an account, keeping its storage
+    ///
+    /// # Arguments
+    /// * `address` - Address of the account
+    /// * `nonce` - Nonce of the account
+    /// * `balance` - Balance in wei, a big-endian 256-bit integer
+    /// * `code_hash` - Hash of the account's code, [`EMPTY_CODE_HASH`] for none
+    pub fn set_account(
+        &mut self,
+        address: [u8; 20],
+        nonce: u64,
+        balance: [u8; 32],
+        code_hash: [u8; 32],
+    ) {
+        let entry = self.entry(address);
+        entry.nonce = nonce;
+        entry.balance = balance;
+        entry.code_hash = code_hash;
+    }
+
+    /// Sets a storage slot of an account, creating the account if needed
+    ///
+    /// Setting a slot to zero removes it from the storage trie.
+    pub fn set_storage(
+        &mut self,
+        address: [u8; 20],
+        slot: [u8; 32],
+        value: [u8; 32],
+    ) -> Result<()> {
+        let storage = &mut self.entry(address).storage;
+        let key = keccak256(&slot);
+        if value == [0; 32] {
+            storage.delete(&key)?;
+        } else {
+            storage.insert(key, encode_scalar(&value))?;
+        }
+        Ok(())
+    }
+
+    /// Returns an account, or `None` if it does not exist
+    pub fn account(&self, address: &[u8; 20]) -> Result<Option<Account>> {
+        self.accounts
+            .get(address)
+            .map(AccountEntry::account)
+            .transpose()
+    }
+
+    /// Computes the state root
+    pub fn state_root(&self) -> Result<Vec<u8>> {
+        Ok(self.state_trie()?.root_hash().to_vec())
+    }
+
+    /// Generates a proof of an account and some of its storage slots
+    ///
+    /// The account and slots do not need to exist, their absence is proven
+    /// instead, as `eth_getProof` does.
+    ///
+    /// # Arguments
+    /// * `address` - Address of the account
+    /// * `slots` - Storage slots to prove
+    pub fn get_proof(&self, address: [u8; 20], slots: &[[u8; 32]]) -> Result<EthProof> {
+        let account_proof = self.state_trie()?.prove(&keccak256(&address))?;
+
+        let entry = self.accounts.get(&address);
+        let account = match entry {
+            Some(entry) => entry.account()?,
+            None => Account {
+                nonce: 0,
+                balance: [0; 32],
+                storage_hash: EMPTY_ROOT,
+                code_hash: EMPTY_CODE_HASH,
+            },
+        };
+
+        let storage_proof = slots
+            .iter()
+            .map(|slot| {
+                let key = keccak256(slot);
+                let (value, proof) = match entry {
+                    Some(entry) => {
+                        let value = match entry.storage.get(&key)? {
+                            Some(encoded) => decode_scalar(&decode(encoded)?)?,
+                            None => [0; 32],
+                        };
+                        (value, entry.storage.generate_eth_proof(&key)?)
+                    }
+                    None => ([0; 32], Vec::new()),
+                };
+                Ok(StorageProof {
+                    key: *slot,
+                    value,
+                    proof,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(EthProof {
+            address,
+            account_proof,
+            balance: account.balance,
+            code_hash: account.code_hash,
+            nonce: account.nonce,
+            storage_hash: account.storage_hash,
+            storage_proof,
+        })
+    }
+
+    /// Returns the entry of an account, creating an empty account if needed
+    ///
+    /// The entry may be changed, so the state trie is dropped.
+    fn entry(&mut self, address: [u8; 20]) -> &mut AccountEntry {
+        self.state.take();
+        self.accounts
+            .entry(address)
+            .or_insert_with(|| AccountEntry {
+                nonce: 0,
+                balance: [0; 32],
+                code_hash: EMPTY_CODE_HASH,
+                storage: PatriciaMerkleTrie::with_mode(HashMode::Ethereum),
+            })
+    }
+
+    /// Returns the state trie, building it if the state changed
+    ///
+    /// Storage roots come from the storage tries, which cache their own
+    /// encoding, so only changed accounts rebuild their storage trie.
+    fn state_trie(&self) -> Result<&EthTrie> {
+        if let Some(trie) = self.state.get() {
+            return Ok(trie);
+        }
+        let mut entries = self
+            .accounts
+            .iter()
+            .map(|(address, entry)| Ok((keccak256(address), entry.account()?.encode())))
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort();
+        let trie = EthTrie::new(entries.iter().map(|(k, v)| (k.clone(), v.as_slice())));
+        Ok(self.state.get_or_init(|| trie))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wei(amount: u128) -> [u8; 32] {
+        let mut balance = [0; 32];
+        balance[16..].copy_from_slice(&amount.to_be_bytes());
+        balance
+    }
+
+    #[test]
+    fn test_empty_code_hash() {
+        assert_eq!(keccak256(&[]), EMPTY_CODE_HASH.to_vec());
+    }
+
+    #[test]
+    fn test_account_round_trip() -> Result<()> {
+        let account = Account {
+            nonce: 7,
+            balance: [0; 32],
+            storage_hash: EMPTY_ROOT,
+            code_hash: EMPTY_CODE_HASH,
+        };
+        let encoded = account.encode();
+        // A zero balance is encoded as the empty string
+        assert_eq!(encoded[2..4], [0x07, 0x80]);
+        assert_eq!(Account::decode(&encoded)?, account);
+
+        assert!(Account::decode(&encode_list(&[encode_bytes(&[0, 7])])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_proofs_verify() -> Result<()> {
+        let mut state = EthState::new();
+        for i in 0..20u8 {
+            state.set_account([i; 20], i as u64, wei(i as u128 * 1000), EMPTY_CODE_HASH);
+        }
+        state.set_storage([3; 20], [1; 32], [0xff; 32])?;
+        state.set_storage([3; 20], [2; 32], [0x01; 32])?;
+        let root = state.state_root()?;
+
+        let proof = state.get_proof([3; 20], &[[1; 32], [9; 32]])?;
+        assert_eq!(proof.nonce, 3);
+        assert_eq!(proof.storage_proof[0].value, [0xff; 32]);
+        assert_eq!(proof.storage_proof[1].value, [0; 32]);
+        assert!(proof.verify(&root)?);
+
+        // A missing account is proven absent
+        let missing = state.get_proof([0xee; 20], &[[1; 32]])?;
+        assert!(missing.verify(&root)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wide_balance() -> Result<()> {
+        // More wei than fits in 128 bits
+        let mut balance = [0; 32];
+        balance[4] = 0x01;
+        balance[31] = 0x2a;
+        let account = Account {
+            nonce: 1,
+            balance,
+            storage_hash: EMPTY_ROOT,
+            code_hash: EMPTY_CODE_HASH,
+        };
+        assert_eq!(Account::decode(&account.encode())?, account);
+
+        let mut state = EthState::new();
+        state.set_account([1; 20], 1, balance, EMPTY_CODE_HASH);
+        state.set_account([2; 20], 2, wei(u128::MAX), EMPTY_CODE_HASH);
+        let proof = state.get_proof([1; 20], &[])?;
+        assert_eq!(proof.balance, balance);
+        assert!(proof.verify(&state.state_root()?)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_proofs_follow_changes() -> Result<()> {
+        let mut state = EthState::new();
+        state.set_storage([1; 20], [1; 32], [0x11; 32])?;
+        let before = state.state_root()?;
+        assert!(state.get_proof([1; 20], &[[1; 32]])?.verify(&before)?);
+
+        state.set_storage([1; 20], [1; 32], [0x22; 32])?;
+        let after = state.state_root()?;
+        assert_ne!(after, before);
+        let proof = state.get_proof([1; 20], &[[1; 32]])?;
+        assert_eq!(proof.storage_proof[0].value, [0x22; 32]);
+        assert!(proof.verify(&after)?);
+        assert!(proof.verify(&before).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrong_claims_rejected() -> Result<()> {
+        let mut state = EthState::new();
+        state.set_account([1; 20], 1, wei(100), EMPTY_CODE_HASH);
+        state.set_account([2; 20], 2, wei(200), EMPTY_CODE_HASH);
+        state.set_storage([1; 20], [5; 32], [6; 32])?;
+        let root = state.state_root()?;
+        let proof = state.get_proof([1; 20], &[[5; 32]])?;
+
+        let mut wrong_balance = proof.clone();
+        wrong_balance.balance = wei(101);
+        assert!(!wrong_balance.verify(&root)?);
+
+        let mut wrong_value = proof.clone();
+        wrong_value.storage_proof[0].value = [7; 32];
+        assert!(!wrong_value.verify(&root)?);
+
+        // The proof of another account does not prove this one
+        let mut wrong_address = proof;
+        wrong_address.address = [2; 20];
+        assert!(!matches!(wrong_address.verify(&root), Ok(true)));
+        Ok(())
+    }
+}
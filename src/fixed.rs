@@ -0,0 +1,137 @@
+//! Fixed-length key trie.
+//!
+//! A [`FixedKeyTrie`] only accepts keys of exactly `N` bytes, such as
+//! 20-byte addresses or 32-byte hashes. The key length is enforced by the
+//! type system, so no runtime length validation is performed and the
+//! `KeyTooShort`/`KeyTooLong` errors cannot occur.
+
+use crate::{error::Result, trie::PatriciaMerkleTrie};
+
+/// A Patricia Merkle Trie with keys of a fixed length of `N` bytes
+///
+/// # Type Parameters
+/// - `N`: Length of every key in bytes
+/// - `V`: Value type that can be converted to byte slices
+///
+/// # Examples
+/// ```
+/// # use patricia_merkle_trie::{FixedKeyTrie, Result};
+/// # fn main() -> Result<()> {
+/// let mut trie: FixedKeyTrie<20, Vec<u8>> = FixedKeyTrie::new();
+/// trie.insert([0xaa; 20], b"balance".to_vec())?;
+/// assert_eq!(trie.get(&[0xaa; 20])?.unwrap(), b"balance");
+/// # Ok(())
+/// # }
+/// ```
+pub struct FixedKeyTrie<const N: usize, V> {
+    trie: PatriciaMerkleTrie<Vec<u8>, V>,
+}
+
+impl<const N: usize, V> FixedKeyTrie<N, V>
+where
+    V: Clone + AsRef<[u8]> + std::fmt::Debug,
+{
+    /// Creates a new empty fixed-length key trie
+    pub fn new() -> Self {
+        FixedKeyTrie {
+            trie: PatriciaMerkleTrie::new(),
+        }
+    }
+
+    /// Inserts a key-value pair into the trie
+    ///
+    /// # Arguments
+    /// * `key` - The key to insert
+    /// * `value` - The value to associate with the key
+    pub fn insert(&mut self, key: [u8; N], value: V) -> Result<()> {
+        self.trie.insert_unchecked(key.to_vec(), value)
+    }
+
+    /// Retrieves a value by key from the trie
+    ///
+    /// # Returns
+    /// * `Ok(Some(&V))` if the key exists
+    /// * `Ok(None)` if the key doesn't exist
+    pub fn get(&self, key: &[u8; N]) -> Result<Option<&V>> {
+        self.trie.get_unchecked(&key.to_vec())
+    }
+
+    /// Deletes a key-value pair from the trie
+    ///
+    /// # Returns
+    /// * `Ok(Some(V))` if the key was found and deleted
+    /// * `Ok(None)` if the key didn't exist
+    pub fn delete(&mut self, key: &[u8; N]) -> Result<Option<V>> {
+        self.trie.delete_unchecked(&key.to_vec())
+    }
+
+    /// Computes the cryptographic hash of the entire trie
+    pub fn root_hash(&self) -> Result<Vec<u8>> {
+        self.trie.root_hash()
+    }
+
+    /// Returns an iterator over all key-value pairs in key order
+    pub fn iter(&self) -> impl Iterator<Item = ([u8; N], &V)> + '_ {
+        self.trie.iter().map(|(key, value)| {
+            let mut fixed = [0u8; N];
+            fixed.copy_from_slice(&key);
+            (fixed, value)
+        })
+    }
+
+    /// Returns the underlying trie
+    pub fn trie(&self) -> &PatriciaMerkleTrie<Vec<u8>, V> {
+        &self.trie
+    }
+}
+
+impl<const N: usize, V> Default for FixedKeyTrie<N, V>
+where
+    V: Clone + AsRef<[u8]> + std::fmt::Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_keys() -> Result<()> {
+        let mut trie: FixedKeyTrie<20, Vec<u8>> = FixedKeyTrie::new();
+        let mut a = [0u8; 20];
+        let mut b = [0u8; 20];
+        a[19] = 1;
+        b[19] = 2;
+        trie.insert(a, vec![1])?;
+        trie.insert(b, vec![2])?;
+
+        assert_eq!(trie.get(&a)?, Some(&vec![1]));
+        assert_eq!(trie.delete(&a)?, Some(vec![1]));
+        assert_eq!(trie.get(&a)?, None);
+        assert_eq!(trie.iter().map(|(k, _)| k).collect::<Vec<_>>(), vec![b]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_keys_longer_than_variable_limit() -> Result<()> {
+        let mut trie: FixedKeyTrie<48, Vec<u8>> = FixedKeyTrie::new();
+        trie.insert([3; 48], vec![1])?;
+        assert_eq!(trie.get(&[3; 48])?, Some(&vec![1]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_variable_length_trie() -> Result<()> {
+        let mut fixed: FixedKeyTrie<32, Vec<u8>> = FixedKeyTrie::new();
+        let mut variable = PatriciaMerkleTrie::new();
+        for i in 0..8u8 {
+            fixed.insert([i; 32], vec![i])?;
+            variable.insert(vec![i; 32], vec![i])?;
+        }
+        assert_eq!(fixed.root_hash()?, variable.root_hash()?);
+        Ok(())
+    }
+}
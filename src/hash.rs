@@ -1,119 +1,166 @@
 //! Cryptographic hashing functionality for the Patricia Merkle Trie.
 //!
-//! This module provides hash functions for different node types in the trie,
-//! ensuring each type has a unique prefix to prevent collisions between
-//! different node types with the same content.
+//! This module provides the encodings of the different node types in the
+//! trie and the hash over them, ensuring each type has a unique prefix to
+//! prevent collisions between different node types with the same content.
+//!
+//! # Node Layout
+//! Each node is encoded with the following byte layout and hashed with
+//! SHA-256, where
+//! `len(x)` is the length of `x` as a 4-byte big-endian integer:
+//!
+//! | Node      | Layout                                                               |
+//! |-----------|----------------------------------------------------------------------|
+//! | Leaf      | `0x00 len(path) path len(value) value`                               |
+//! | Branch    | `0x01 len(children) (nibble len(ref) ref)* value_flag [len(value) value]` |
+//! | Empty     | `0x02`                                                               |
+//! | Extension | `0x03 len(prefix) prefix len(child_ref) child_ref`                   |
+//!
+//! Branch children are ordered by nibble. The `value_flag` is `0x00` when
+//! the branch holds no value and `0x01` when it does, in which case the
+//! value follows, so an absent value and an empty value hash differently.
+//!
+//! # Child References
+//! Branches and extensions refer to their children by reference: a child
+//! whose encoding is shorter than [`HASH_LEN`] bytes is embedded as is,
+//! any other child is referenced by the hash of its encoding. Embedded
+//! nodes are not hashed or stored separately. The root hash is always the
+//! hash of the root's encoding.
 
 use crate::error::{Result, TrieError};
-use sha2::{Digest, Sha256};
+pub(crate) use crate::verify::push_len;
+pub use crate::verify::{decode_node, hash_data, DecodedNode, HASH_LEN};
 
-/// Computes a SHA-256 hash of arbitrary data
+/// Returns the reference to a child node with the given encoding
 ///
 /// # Arguments
-/// * `data` - Byte slice to hash
+/// * `encoded` - Encoding of the child node
 ///
 /// # Returns
-/// A vector containing the 32-byte hash
-pub fn hash_data(data: &[u8]) -> Vec<u8> {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    hasher.finalize().to_vec()
+/// The encoding itself if it is shorter than [`HASH_LEN`], its hash otherwise
+pub fn node_reference(encoded: Vec<u8>) -> Vec<u8> {
+    if encoded.len() < HASH_LEN {
+        encoded
+    } else {
+        hash_data(&encoded)
+    }
 }
 
-/// Computes a SHA-256 hash of a leaf node
+/// Encodes a leaf node
 ///
-/// Prepends a 0x00 byte to distinguish leaf node hashes from other types.
+/// Prepends a 0x00 byte to distinguish leaf nodes from other types.
 ///
 /// # Arguments
-/// * `key` - Key stored in the leaf (in nibbles)
+/// * `path` - Remaining key nibbles stored in the leaf
 /// * `value` - Value stored in the leaf
 ///
+/// The path is empty when the leaf sits directly below a branch that
+/// consumes its last nibble, or for a value stored at the root of the trie.
+///
 /// # Returns
-/// * `Ok(Vec<u8>)` - 32-byte hash of the leaf node
-/// * `Err(TrieError)` - If key is empty
-pub fn hash_leaf(key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
-    println!("Hashing leaf - key: {:?}, value: {:?}", key, value);
-    if key.is_empty() {
-        return Err(TrieError::InvalidKey);
-    }
-    let mut hasher = Sha256::new();
-    // Add a prefix byte to distinguish leaf node hashes
-    hasher.update([0x00]);
-    // Add key length and key bytes
-    hasher.update(&[key.len() as u8]);
-    hasher.update(key);
+/// The encoded leaf node
+pub fn encode_leaf(path: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(9 + path.len() + value.len());
+    // Add a prefix byte to distinguish leaf nodes
+    out.push(0x00);
+    // Add path length and path bytes
+    push_len(&mut out, path.len());
+    out.extend_from_slice(path);
     // Add value length and value bytes
-    hasher.update(&[value.len() as u8]);
-    hasher.update(value);
-    let hash = hasher.finalize().to_vec();
-    println!("Leaf hash result: {:?}", hash);
-    Ok(hash)
+    push_len(&mut out, value.len());
+    out.extend_from_slice(value);
+    out
 }
 
-/// Computes a SHA-256 hash of a branch node
+/// Encodes a branch node
 ///
-/// Prepends a 0x01 byte to distinguish branch node hashes from other types.
-/// Sorts children by key before hashing to ensure consistent hashes.
+/// Prepends a 0x01 byte to distinguish branch nodes from other types.
+/// Children must be sorted by key, which branch nodes maintain, so the
+/// encoding is consistent without sorting.
 ///
 /// # Arguments
-/// * `prefix` - Common prefix of the branch (in nibbles)
-/// * `children_data` - Vector of (key, hash) pairs for each child
+/// * `children_data` - Vector of (key, reference) pairs for each child, sorted by key
 /// * `value` - Optional value stored at the branch
 ///
 /// # Returns
-/// * `Ok(Vec<u8>)` - 32-byte hash of the branch node
-/// * `Err(TrieError)` - If children_data is empty
-pub fn hash_branch(
-    prefix: &[u8],
-    children_data: &[(u8, Vec<u8>)],
-    value: &[u8],
-) -> Result<Vec<u8>> {
-    println!("Hashing branch - prefix: {:?}, children: {:?}, value: {:?}", prefix, children_data, value);
+/// * `Ok(Vec<u8>)` - The encoded branch node
+/// * `Err(TrieError)` - If children_data is empty or not strictly sorted by key
+pub fn encode_branch(children_data: &[(u8, Vec<u8>)], value: Option<&[u8]>) -> Result<Vec<u8>> {
     if children_data.is_empty() {
         return Err(TrieError::InvalidBranch);
     }
-    let mut hasher = Sha256::new();
-    // Add a prefix byte to distinguish branch node hashes
-    hasher.update([0x01]);
-    // Add prefix length and prefix bytes
-    hasher.update(&[prefix.len() as u8]);
-    hasher.update(prefix);
-    // Sort children by key for consistent hashing
-    let mut sorted_children: Vec<_> = children_data.to_vec();
-    sorted_children.sort_by_key(|&(k, _)| k);
+    if children_data.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+        return Err(TrieError::InvalidBranch);
+    }
+    let mut out = Vec::new();
+    // Add a prefix byte to distinguish branch nodes
+    out.push(0x01);
     // Add number of children
-    hasher.update(&[sorted_children.len() as u8]);
-    for (key, child_hash) in sorted_children {
-        println!("Processing child - key: {:?}, hash: {:?}", key, child_hash);
-        hasher.update([key]);
-        hasher.update(&[child_hash.len() as u8]);
-        hasher.update(&child_hash);
+    push_len(&mut out, children_data.len());
+    for (key, child_ref) in children_data {
+        out.push(*key);
+        push_len(&mut out, child_ref.len());
+        out.extend_from_slice(child_ref);
     }
-    // Add the branch value to the hash
-    hasher.update(&[value.len() as u8]);
-    hasher.update(value);
-    let hash = hasher.finalize().to_vec();
-    println!("Branch hash result: {:?}", hash);
-    Ok(hash)
+    // Add the branch value, flagging whether it is present
+    match value {
+        Some(value) => {
+            out.push(0x01);
+            push_len(&mut out, value.len());
+            out.extend_from_slice(value);
+        }
+        None => out.push(0x00),
+    }
+    Ok(out)
 }
 
-/// Computes a SHA-256 hash of an empty node
+/// Encodes an extension node
+///
+/// Prepends a 0x03 byte to distinguish extension nodes from other types.
 ///
-/// Prepends a 0x02 byte to distinguish empty node hashes from other types.
+/// # Arguments
+/// * `prefix` - Path segment of the extension (in nibbles)
+/// * `child_ref` - Reference to the branch the extension points to
 ///
 /// # Returns
-/// A vector containing the 32-byte hash
-pub fn hash_empty() -> Vec<u8> {
-    hash_data(&[0x02]) // Special prefix for empty nodes
+/// * `Ok(Vec<u8>)` - The encoded extension node
+/// * `Err(TrieError)` - If prefix is empty
+pub fn encode_extension(prefix: &[u8], child_ref: &[u8]) -> Result<Vec<u8>> {
+    if prefix.is_empty() {
+        return Err(TrieError::InvalidPrefix);
+    }
+    let mut out = Vec::with_capacity(9 + prefix.len() + child_ref.len());
+    // Add a prefix byte to distinguish extension nodes
+    out.push(0x03);
+    // Add prefix length and prefix bytes
+    push_len(&mut out, prefix.len());
+    out.extend_from_slice(prefix);
+    // Add the reference to the child branch
+    push_len(&mut out, child_ref.len());
+    out.extend_from_slice(child_ref);
+    Ok(out)
+}
+
+/// Encodes an empty node as the single byte 0x02
+pub fn encode_empty() -> Vec<u8> {
+    vec![0x02]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn hash_leaf(path: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+        Ok(hash_data(&encode_leaf(path, value)))
+    }
+
+    fn hash_branch(children: &[(u8, Vec<u8>)], value: Option<&[u8]>) -> Result<Vec<u8>> {
+        Ok(hash_data(&encode_branch(children, value)?))
+    }
+
     #[test]
     fn test_hash_leaf() {
-        assert!(hash_leaf(&[], &[1]).is_err());
+        assert_ne!(hash_leaf(&[], &[1]).unwrap(), hash_leaf(&[], &[2]).unwrap());
 
         let hash1 = hash_leaf(&[1], &[2]).unwrap();
         let hash2 = hash_leaf(&[1], &[2]).unwrap();
@@ -125,30 +172,104 @@ mod tests {
 
     #[test]
     fn test_hash_branch() {
-        assert!(hash_branch(&[1], &[], &[1]).is_err());
+        assert!(hash_branch(&[], Some(&[1])).is_err());
 
         let children1 = vec![(1, vec![1]), (2, vec![2])];
         let children2 = vec![(2, vec![2]), (1, vec![1])];
 
-        let hash1 = hash_branch(&[1], &children1, &[1]).unwrap();
-        let hash2 = hash_branch(&[1], &children2, &[1]).unwrap();
-
-        // Same content in different order should produce same hash
+        let hash1 = hash_branch(&children1, Some(&[1])).unwrap();
+        let hash2 = hash_branch(&children1, Some(&[1])).unwrap();
         assert_eq!(hash1, hash2);
+
+        // Children out of order or with duplicate keys are rejected
+        assert!(hash_branch(&children2, Some(&[1])).is_err());
+        assert!(hash_branch(&[(1, vec![1]), (1, vec![2])], None).is_err());
+    }
+
+    #[test]
+    fn test_encode_extension() {
+        assert!(encode_extension(&[], &[1]).is_err());
+
+        let encoded1 = encode_extension(&[1], &[2]).unwrap();
+        let encoded2 = encode_extension(&[1, 2], &[2]).unwrap();
+        assert_ne!(encoded1, encoded2);
+    }
+
+    #[test]
+    fn test_decode_node() {
+        let leaf = encode_leaf(&[1, 2], &[3]);
+        assert_eq!(
+            decode_node(&leaf).unwrap(),
+            DecodedNode::Leaf {
+                path: vec![1, 2],
+                value: vec![3]
+            }
+        );
+
+        let children = vec![(1, vec![4; 32]), (7, leaf.clone())];
+        let branch = encode_branch(&children, Some(&[])).unwrap();
+        assert_eq!(
+            decode_node(&branch).unwrap(),
+            DecodedNode::Branch {
+                children,
+                value: Some(vec![])
+            }
+        );
+
+        let extension = encode_extension(&[9], &[5; 32]).unwrap();
+        assert_eq!(
+            decode_node(&extension).unwrap(),
+            DecodedNode::Extension {
+                prefix: vec![9],
+                child: vec![5; 32]
+            }
+        );
+        assert_eq!(decode_node(&encode_empty()).unwrap(), DecodedNode::Empty);
+    }
+
+    #[test]
+    fn test_decode_node_invalid() {
+        let leaf = encode_leaf(&[1, 2], &[3]);
+        assert!(decode_node(&[]).is_err());
+        assert!(decode_node(&[0x04]).is_err());
+        assert!(decode_node(&leaf[..leaf.len() - 1]).is_err());
+        assert!(decode_node(&[leaf.as_slice(), &[0]].concat()).is_err());
+        // Extensions with an empty prefix and unsorted branches
+        assert!(decode_node(&[0x03, 0, 0, 0, 0, 0, 0, 0, 1, 0]).is_err());
+        let mut unsorted = vec![0x01, 0, 0, 0, 2];
+        unsorted.extend_from_slice(&[2, 0, 0, 0, 1, 0, 1, 0, 0, 0, 1, 0, 0]);
+        assert!(decode_node(&unsorted).is_err());
+    }
+
+    #[test]
+    fn test_node_reference() {
+        let short = encode_leaf(&[1], &[2]);
+        assert!(short.len() < HASH_LEN);
+        assert_eq!(node_reference(short.clone()), short);
+
+        let long = encode_leaf(&[1], &[2; 32]);
+        assert_eq!(node_reference(long.clone()), hash_data(&long));
     }
 
     #[test]
     fn test_hash_uniqueness() {
-        let empty = hash_empty();
+        let empty = hash_data(&encode_empty());
         let leaf = hash_leaf(&[1], &[2]).unwrap();
-        let branch = hash_branch(&[1], &[(1, vec![2])], &[]).unwrap();
+        let branch = hash_branch(&[(1, vec![2])], None).unwrap();
+        let extension = hash_data(&encode_extension(&[1], &[2]).unwrap());
 
         assert_ne!(empty, leaf);
         assert_ne!(empty, branch);
         assert_ne!(leaf, branch);
+        assert_ne!(extension, leaf);
+        assert_ne!(extension, branch);
 
         // Test that different branch values produce different hashes
-        let branch2 = hash_branch(&[1], &[(1, vec![2])], &[3]).unwrap();
+        let branch2 = hash_branch(&[(1, vec![2])], Some(&[3])).unwrap();
         assert_ne!(branch, branch2);
+
+        // An empty value differs from no value at all
+        let branch3 = hash_branch(&[(1, vec![2])], Some(&[])).unwrap();
+        assert_ne!(branch, branch3);
     }
 }
@@ -9,6 +9,11 @@
 //! - Cryptographic verification of contents
 //! - Generic key and value types
 //! - Full CRUD operations (Create, Read, Update, Delete)
+//! - Secure mode with hashed keys for user-controlled keys
+//! - Order-preserving encodings for typed keys
+//! - RLP encoding and decoding
+//! - Ethereum-compatible root hashes
+//! - Account and storage proofs compatible with `eth_getProof`
 //!
 //! # Example
 //! ```rust
@@ -30,19 +35,73 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! # `no_std`
+//! Proof verification lives in the [`verify`] module, which only needs
+//! `alloc`. Disabling the default `std` feature builds the crate without
+//! the standard library, with [`verify`] as its only module.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
+/// Order-preserving encodings for typed keys
+#[cfg(feature = "std")]
+mod codec;
 /// Error types and Result type alias
+#[cfg(feature = "std")]
 mod error;
+/// Ethereum-compatible root hash computation
+#[cfg(feature = "std")]
+pub mod eth;
+/// Account and storage proofs in the shape of `eth_getProof`
+#[cfg(feature = "std")]
+pub mod eth_proof;
+/// Trie with fixed-length keys
+#[cfg(feature = "std")]
+mod fixed;
 /// Cryptographic hashing functionality
+#[cfg(feature = "std")]
 mod hash;
+/// Insertion and deletion shared by full and partial tries
+#[cfg(feature = "std")]
+mod mutation;
 /// Core node types and implementations
+#[cfg(feature = "std")]
 mod node;
+/// Partial tries revealed from proof nodes
+#[cfg(feature = "std")]
+mod partial;
 /// Merkle proof generation and verification
+#[cfg(feature = "std")]
 pub mod proof;
+/// RLP encoding and decoding
+#[cfg(feature = "std")]
+pub mod rlp;
+/// Secure trie mode with hashed keys
+#[cfg(feature = "std")]
+mod secure;
 /// Main trie implementation
+#[cfg(feature = "std")]
 mod trie;
 /// Utility functions for trie operations
+#[cfg(feature = "std")]
 mod utils;
+/// Proof verification without the standard library
+pub mod verify;
+/// Witnesses for state transitions
+#[cfg(feature = "std")]
+pub mod witness;
 
+#[cfg(feature = "std")]
+pub use codec::{KeyCodec, TypedTrie};
+#[cfg(feature = "std")]
 pub use error::{Result, TrieError};
-pub use trie::PatriciaMerkleTrie;
+#[cfg(feature = "std")]
+pub use fixed::FixedKeyTrie;
+#[cfg(feature = "std")]
+pub use partial::PartialTrie;
+#[cfg(feature = "std")]
+pub use secure::SecureTrie;
+#[cfg(feature = "std")]
+pub use trie::{HashMode, PatriciaMerkleTrie};
@@ -0,0 +1,438 @@
+//! Insertion and deletion shared by full and partial tries.
+//!
+//! Full and partial tries must split and collapse nodes in exactly the same
+//! way to keep the canonical shape described on [`Node`], and therefore to
+//! agree on the root hash. The updates are written once here over
+//! [`NodeBuilder`], which opens and builds the nodes of either kind of trie.
+//!
+//! A partial trie keeps the subtrees it has not revealed as their hashes.
+//! These open as [`NodeView::Unrevealed`], and updates that need to look
+//! inside one fail with [`TrieError::MissingWitness`].
+//!
+//! [`Node`]: crate::node::Node
+
+use std::collections::BTreeMap;
+
+use crate::{
+    node::{Children, Node},
+    utils::common_prefix,
+    Result, TrieError,
+};
+
+/// Contents of a node opened for an update
+pub(crate) enum NodeView<N, V, C> {
+    Empty,
+    Leaf {
+        path: Vec<u8>,
+        value: V,
+    },
+    Extension {
+        prefix: Vec<u8>,
+        child: Box<N>,
+    },
+    Branch {
+        children: C,
+        value: Option<V>,
+    },
+    /// A subtree whose contents are not known
+    Unrevealed(N),
+}
+
+/// Children of an opened branch, indexed by nibble
+pub(crate) trait ChildMap<N>: Default {
+    /// Returns the number of children
+    fn len(&self) -> usize;
+
+    /// Sets the child at a nibble
+    fn insert(&mut self, nibble: u8, child: N);
+
+    /// Removes the child at a nibble
+    fn remove(&mut self, nibble: u8) -> Option<N>;
+
+    /// Removes the child with the lowest nibble
+    fn pop_first(&mut self) -> Option<(u8, N)>;
+}
+
+/// Opens and builds the nodes of a trie during an update
+pub(crate) trait NodeBuilder: Sized {
+    /// Node type of the trie
+    type Node;
+    /// Value type of the trie
+    type Value;
+    /// Children of a branch
+    type Children: ChildMap<Self::Node>;
+
+    /// Opens a node to look at its contents
+    fn open(node: Self::Node) -> Result<View<Self>>;
+
+    /// Puts the contents of a node back together, without recording it
+    fn close(view: View<Self>) -> Self::Node;
+
+    /// Records a node created or changed by an update
+    fn store(&mut self, node: Self::Node) -> Result<Self::Node>;
+
+    /// Checks if the node is empty
+    fn is_empty(node: &Self::Node) -> bool;
+}
+
+/// Contents of a node of the trie built by `B`
+pub(crate) type View<B> =
+    NodeView<<B as NodeBuilder>::Node, <B as NodeBuilder>::Value, <B as NodeBuilder>::Children>;
+
+/// Builds and records a node
+fn build<B: NodeBuilder>(builder: &mut B, view: View<B>) -> Result<B::Node> {
+    let node = B::close(view);
+    builder.store(node)
+}
+
+/// Inserts `value` under `key` below `node`, which sits `depth` nibbles
+/// down the key
+///
+/// # Returns
+/// * `Ok(Node)` - The node replacing `node`
+/// * `Err(TrieError::MissingWitness)` - If the insertion reaches an
+///   unrevealed subtree
+pub(crate) fn insert<B: NodeBuilder>(
+    builder: &mut B,
+    node: B::Node,
+    key: &[u8],
+    depth: usize,
+    value: B::Value,
+) -> Result<B::Node> {
+    let nibbles = &key[depth..];
+    match B::open(node)? {
+        NodeView::Empty => build(
+            builder,
+            NodeView::Leaf {
+                path: nibbles.to_vec(),
+                value,
+            },
+        ),
+        NodeView::Leaf {
+            path,
+            value: existing_value,
+        } => {
+            if path[..] == *nibbles {
+                // Same key, just update value
+                return build(builder, NodeView::Leaf { path, value });
+            }
+
+            let prefix_len = common_prefix(&path, nibbles);
+            let mut children = B::Children::default();
+            let mut branch_value = None;
+
+            // Keep the existing leaf below the branch if it has remaining
+            // nibbles, otherwise its value moves onto the branch
+            if prefix_len < path.len() {
+                let existing = build(
+                    builder,
+                    NodeView::Leaf {
+                        path: path[prefix_len + 1..].to_vec(),
+                        value: existing_value,
+                    },
+                )?;
+                children.insert(path[prefix_len], existing);
+            } else {
+                branch_value = Some(existing_value);
+            }
+            split(
+                builder,
+                children,
+                branch_value,
+                key,
+                depth,
+                prefix_len,
+                value,
+            )
+        }
+        NodeView::Extension { prefix, child } => {
+            let prefix_len = common_prefix(&prefix, nibbles);
+            if prefix_len == prefix.len() {
+                // The whole segment matches, continue in the branch below
+                let child = insert(builder, *child, key, depth + prefix_len, value)?;
+                return build(
+                    builder,
+                    NodeView::Extension {
+                        prefix,
+                        child: Box::new(child),
+                    },
+                );
+            }
+
+            // Split the extension where the paths diverge. The rest of the
+            // segment keeps pointing at the branch below, which is never
+            // opened, so it may be unrevealed.
+            let rest = &prefix[prefix_len + 1..];
+            let existing = if rest.is_empty() {
+                *child
+            } else {
+                build(
+                    builder,
+                    NodeView::Extension {
+                        prefix: rest.to_vec(),
+                        child,
+                    },
+                )?
+            };
+            let mut children = B::Children::default();
+            children.insert(prefix[prefix_len], existing);
+            split(builder, children, None, key, depth, prefix_len, value)
+        }
+        NodeView::Branch {
+            mut children,
+            value: existing_value,
+        } => {
+            let Some(&nibble) = nibbles.first() else {
+                // Update branch value
+                return build(
+                    builder,
+                    NodeView::Branch {
+                        children,
+                        value: Some(value),
+                    },
+                );
+            };
+
+            // Recursively insert into the child below the branch nibble
+            let child = match children.remove(nibble) {
+                Some(child) => insert(builder, child, key, depth + 1, value)?,
+                None => build(
+                    builder,
+                    NodeView::Leaf {
+                        path: nibbles[1..].to_vec(),
+                        value,
+                    },
+                )?,
+            };
+            children.insert(nibble, child);
+            build(
+                builder,
+                NodeView::Branch {
+                    children,
+                    value: existing_value,
+                },
+            )
+        }
+        NodeView::Unrevealed(_) => Err(TrieError::MissingWitness(key[..depth].to_vec())),
+    }
+}
+
+/// Adds the new key to the branch replacing a node where the paths
+/// diverge, `prefix_len` nibbles below `depth`
+///
+/// The new key gets a leaf below the branch, or stores its value on the
+/// branch if it ends at the split point.
+fn split<B: NodeBuilder>(
+    builder: &mut B,
+    mut children: B::Children,
+    mut branch_value: Option<B::Value>,
+    key: &[u8],
+    depth: usize,
+    prefix_len: usize,
+    value: B::Value,
+) -> Result<B::Node> {
+    let nibbles = &key[depth..];
+    if prefix_len < nibbles.len() {
+        let leaf = build(
+            builder,
+            NodeView::Leaf {
+                path: nibbles[prefix_len + 1..].to_vec(),
+                value,
+            },
+        )?;
+        children.insert(nibbles[prefix_len], leaf);
+    } else {
+        branch_value = Some(value);
+    }
+    let branch = build(
+        builder,
+        NodeView::Branch {
+            children,
+            value: branch_value,
+        },
+    )?;
+    extend(
+        builder,
+        nibbles[..prefix_len].to_vec(),
+        branch,
+        &key[..depth],
+    )
+}
+
+/// Deletes `key` below `node`, which sits `depth` nibbles down the key
+///
+/// # Returns
+/// * `Ok((Node, Option<Value>))` - The node replacing `node` and the
+///   deleted value, if any
+/// * `Err(TrieError::MissingWitness)` - If the deletion reaches an
+///   unrevealed subtree, including a sibling that a collapsing branch
+///   merges into its parent
+pub(crate) fn delete<B: NodeBuilder>(
+    builder: &mut B,
+    node: B::Node,
+    key: &[u8],
+    depth: usize,
+) -> Result<(B::Node, Option<B::Value>)> {
+    let nibbles = &key[depth..];
+    match B::open(node)? {
+        NodeView::Empty => Ok((B::close(NodeView::Empty), None)),
+        NodeView::Leaf { path, value } => {
+            if path[..] != *nibbles {
+                return Ok((B::close(NodeView::Leaf { path, value }), None));
+            }
+            Ok((B::close(NodeView::Empty), Some(value)))
+        }
+        NodeView::Extension { prefix, child } => {
+            if !nibbles.starts_with(&prefix) {
+                // Key not below this extension
+                return Ok((B::close(NodeView::Extension { prefix, child }), None));
+            }
+
+            let (child, deleted) = delete(builder, *child, key, depth + prefix.len())?;
+            if deleted.is_none() {
+                let child = Box::new(child);
+                return Ok((B::close(NodeView::Extension { prefix, child }), None));
+            }
+
+            // The branch below may have collapsed, merge it back under the segment
+            let node = extend(builder, prefix, child, &key[..depth])?;
+            Ok((node, deleted))
+        }
+        NodeView::Branch {
+            mut children,
+            value,
+        } => {
+            let Some(&nibble) = nibbles.first() else {
+                // This is the target branch, remove its value
+                let node = collapse(builder, children, None, &key[..depth])?;
+                return Ok((node, value));
+            };
+            let Some(child) = children.remove(nibble) else {
+                return Ok((B::close(NodeView::Branch { children, value }), None));
+            };
+
+            let (child, deleted) = delete(builder, child, key, depth + 1)?;
+            if !B::is_empty(&child) {
+                children.insert(nibble, child);
+            }
+            let node = collapse(builder, children, value, &key[..depth])?;
+            Ok((node, deleted))
+        }
+        NodeView::Unrevealed(_) => Err(TrieError::MissingWitness(key[..depth].to_vec())),
+    }
+}
+
+/// Rebuilds the branch at `path` after a deletion, collapsing it when it
+/// no longer needs to fan out
+///
+/// A branch without children becomes a leaf with an empty path holding its
+/// value (or an empty node), and a branch with a single child and no value
+/// is merged into that child. This keeps the trie shape, and therefore the
+/// root hash, independent of the order of operations.
+fn collapse<B: NodeBuilder>(
+    builder: &mut B,
+    mut children: B::Children,
+    value: Option<B::Value>,
+    path: &[u8],
+) -> Result<B::Node> {
+    match (children.len(), value) {
+        (0, None) => Ok(B::close(NodeView::Empty)),
+        (0, Some(value)) => build(
+            builder,
+            NodeView::Leaf {
+                path: Vec::new(),
+                value,
+            },
+        ),
+        (1, None) => {
+            let (nibble, child) = children.pop_first().ok_or(TrieError::CorruptedBranch)?;
+            if B::is_empty(&child) {
+                return Err(TrieError::CorruptedBranch);
+            }
+            extend(builder, vec![nibble], child, path)
+        }
+        (_, value) => build(builder, NodeView::Branch { children, value }),
+    }
+}
+
+/// Places a node below a path segment starting at `path`
+///
+/// Wraps the node in an extension for a non-empty segment, merging nested
+/// extensions so that an extension always points at a branch. Merging
+/// depends on the type of the node, so it must be revealed unless the
+/// segment is empty.
+fn extend<B: NodeBuilder>(
+    builder: &mut B,
+    mut prefix: Vec<u8>,
+    node: B::Node,
+    path: &[u8],
+) -> Result<B::Node> {
+    if prefix.is_empty() {
+        return Ok(node);
+    }
+    match B::open(node)? {
+        NodeView::Extension {
+            prefix: child_prefix,
+            child,
+        } => {
+            prefix.extend(child_prefix);
+            build(builder, NodeView::Extension { prefix, child })
+        }
+        branch @ NodeView::Branch { .. } => {
+            let child = Box::new(B::close(branch));
+            build(builder, NodeView::Extension { prefix, child })
+        }
+        // Leaves absorb the segment into their own path
+        NodeView::Leaf {
+            path: suffix,
+            value,
+        } => {
+            prefix.extend(suffix);
+            build(
+                builder,
+                NodeView::Leaf {
+                    path: prefix,
+                    value,
+                },
+            )
+        }
+        NodeView::Empty => Ok(B::close(NodeView::Empty)),
+        NodeView::Unrevealed(_) => Err(TrieError::MissingWitness([path, &prefix].concat())),
+    }
+}
+
+impl<K, V> ChildMap<Node<K, V>> for Children<K, V> {
+    fn len(&self) -> usize {
+        Children::len(self)
+    }
+
+    fn insert(&mut self, nibble: u8, child: Node<K, V>) {
+        Children::insert(self, nibble, child);
+    }
+
+    fn remove(&mut self, nibble: u8) -> Option<Node<K, V>> {
+        Children::remove(self, nibble)
+    }
+
+    fn pop_first(&mut self) -> Option<(u8, Node<K, V>)> {
+        Children::pop_first(self)
+    }
+}
+
+impl<N> ChildMap<N> for BTreeMap<u8, N> {
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn insert(&mut self, nibble: u8, child: N) {
+        BTreeMap::insert(self, nibble, child);
+    }
+
+    fn remove(&mut self, nibble: u8) -> Option<N> {
+        BTreeMap::remove(self, &nibble)
+    }
+
+    fn pop_first(&mut self) -> Option<(u8, N)> {
+        BTreeMap::pop_first(self)
+    }
+}
@@ -1,37 +1,47 @@
 //! Node types and implementations for the Patricia Merkle Trie.
 //!
 //! This module defines the core node structure used in the trie,
-//! including leaf nodes for storing values and branch nodes for
-//! maintaining the tree structure.
-
-use std::collections::HashMap;
+//! including leaf nodes for storing values, extension nodes for shared
+//! path segments and branch nodes for maintaining the tree structure.
 
 /// Represents a node in the Patricia Merkle Trie
 ///
 /// # Type Parameters
-/// - `K`: Key type that can be converted to and from byte slices
+/// - `K`: Path type that can be converted to and from byte slices
 /// - `V`: Value type that can be converted to byte slices
 ///
 /// # Variants
-/// - `Leaf`: Stores a key-value pair
-/// - `Branch`: Internal node with a prefix and child nodes
+/// - `Leaf`: Stores the remaining key suffix and a value
+/// - `Extension`: Shared path segment leading to a single branch
+/// - `Branch`: Internal node fanning out into child nodes
 /// - `Empty`: Represents absence of a node
+///
+/// # Canonical Form
+/// The trie keeps its nodes in canonical form, so its shape only depends on
+/// its contents:
+/// - An extension has a non-empty prefix and its child is always a branch
+/// - A branch has at least two children, or one child and a value
 #[derive(Debug, Clone)]
 pub enum Node<K, V> {
-    /// Leaf node containing a key-value pair
+    /// Leaf node containing the end of a key and its value
     Leaf {
-        /// The complete key for this leaf
-        key: K,
+        /// Nibbles of the key remaining below the parent node
+        path: K,
         /// The value stored at this leaf
         value: V,
     },
-    /// Branch node containing a common prefix and child nodes
-    Branch {
-        /// Common prefix shared by all children
+    /// Extension node containing a path segment shared by all keys below it
+    Extension {
+        /// Nibbles shared by all keys below this node
         prefix: K,
-        /// Map of nibble to child nodes
-        children: HashMap<u8, Box<Node<K, V>>>,
-        /// Value stored at this branch
+        /// Branch node reached after consuming the prefix
+        child: Box<Node<K, V>>,
+    },
+    /// Branch node containing child nodes indexed by their next nibble
+    Branch {
+        /// Child nodes sorted by nibble
+        children: Children<K, V>,
+        /// Value stored at this branch, for a key ending at this node
         value: V,
     },
     /// Empty node representing absence of data
@@ -59,6 +69,14 @@ where
         matches!(self, Node::Leaf { .. })
     }
 
+    /// Checks if the node is an extension node
+    ///
+    /// # Returns
+    /// `true` if the node is the Extension variant
+    pub fn is_extension(&self) -> bool {
+        matches!(self, Node::Extension { .. })
+    }
+
     /// Checks if the node is a branch node
     ///
     /// # Returns
@@ -78,13 +96,107 @@ where
     /// Gets the children of a branch node
     ///
     /// # Returns
-    /// The children HashMap if this is a branch node, empty HashMap otherwise
-    pub fn into_children(self) -> HashMap<u8, Box<Self>> {
+    /// The children if this is a branch node, no children otherwise
+    pub fn into_children(self) -> Children<K, V> {
         match self {
             Node::Branch { children, .. } => children,
-            _ => HashMap::new(),
+            _ => Children::new(),
+        }
+    }
+}
+
+/// Child nodes of a branch, indexed by nibble
+///
+/// Children are stored inline in a vector kept sorted by nibble, so a
+/// branch only allocates for the children it actually has, and iteration
+/// and hashing visit children in order without sorting.
+#[derive(Debug, Clone)]
+pub struct Children<K, V> {
+    entries: Vec<(u8, Node<K, V>)>,
+}
+
+impl<K, V> Children<K, V> {
+    /// Creates an empty set of children
+    pub fn new() -> Self {
+        Children {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns the number of children
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Checks if there are no children
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the child at a nibble
+    pub fn get(&self, nibble: u8) -> Option<&Node<K, V>> {
+        self.position(nibble)
+            .ok()
+            .map(|index| &self.entries[index].1)
+    }
+
+    /// Sets the child at a nibble
+    ///
+    /// # Returns
+    /// The previous child at the nibble, if any
+    pub fn insert(&mut self, nibble: u8, child: Node<K, V>) -> Option<Node<K, V>> {
+        match self.position(nibble) {
+            Ok(index) => Some(std::mem::replace(&mut self.entries[index].1, child)),
+            Err(index) => {
+                self.entries.insert(index, (nibble, child));
+                None
+            }
         }
     }
+
+    /// Removes the child at a nibble
+    ///
+    /// # Returns
+    /// The removed child, if any
+    pub fn remove(&mut self, nibble: u8) -> Option<Node<K, V>> {
+        self.position(nibble)
+            .ok()
+            .map(|index| self.entries.remove(index).1)
+    }
+
+    /// Removes and returns the child with the lowest nibble
+    pub fn pop_first(&mut self) -> Option<(u8, Node<K, V>)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        Some(self.entries.remove(0))
+    }
+
+    /// Returns an iterator over the children in nibble order
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &Node<K, V>)> {
+        self.entries.iter().map(|(nibble, child)| (*nibble, child))
+    }
+
+    /// Locates a nibble in the sorted entries
+    fn position(&self, nibble: u8) -> std::result::Result<usize, usize> {
+        self.entries.binary_search_by_key(&nibble, |(n, _)| *n)
+    }
+}
+
+impl<K, V> Default for Children<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> FromIterator<(u8, Node<K, V>)> for Children<K, V> {
+    fn from_iter<I: IntoIterator<Item = (u8, Node<K, V>)>>(iter: I) -> Self {
+        let mut children = Children::new();
+        for (nibble, child) in iter {
+            children.insert(nibble, child);
+        }
+        children
+    }
 }
 
 /// Default implementation creating an empty node
@@ -101,14 +213,17 @@ mod tests {
     #[test]
     fn test_node_type_checks() {
         let leaf: Node<Vec<u8>, Vec<u8>> = Node::Leaf {
-            key: vec![1],
+            path: vec![1],
             value: vec![2],
         };
         let branch: Node<Vec<u8>, Vec<u8>> = Node::Branch {
-            prefix: vec![1],
-            children: HashMap::new(),
+            children: Children::new(),
             value: vec![2],
         };
+        let extension: Node<Vec<u8>, Vec<u8>> = Node::Extension {
+            prefix: vec![1],
+            child: Box::new(branch.clone()),
+        };
         let empty: Node<Vec<u8>, Vec<u8>> = Node::Empty;
 
         assert!(leaf.is_leaf());
@@ -117,13 +232,49 @@ mod tests {
 
         assert!(branch.is_branch());
         assert!(!branch.is_leaf());
+        assert!(!branch.is_extension());
         assert!(!branch.is_empty());
 
+        assert!(extension.is_extension());
+        assert!(!extension.is_branch());
+        assert!(!extension.is_leaf());
+
         assert!(empty.is_empty());
         assert!(!empty.is_leaf());
         assert!(!empty.is_branch());
     }
 
+    #[test]
+    fn test_children_sorted() {
+        let leaf = |v: u8| -> Node<Vec<u8>, Vec<u8>> {
+            Node::Leaf {
+                path: vec![v],
+                value: vec![v],
+            }
+        };
+        let mut children: Children<_, _> = [(9, leaf(9)), (1, leaf(1)), (5, leaf(5))]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            children.iter().map(|(n, _)| n).collect::<Vec<_>>(),
+            vec![1, 5, 9]
+        );
+
+        assert!(children.insert(5, leaf(6)).is_some());
+        assert!(children.insert(3, leaf(3)).is_none());
+        assert_eq!(children.len(), 4);
+        assert!(matches!(children.get(5), Some(Node::Leaf { value, .. }) if *value == vec![6]));
+
+        assert!(children.remove(1).is_some());
+        assert!(children.remove(1).is_none());
+        assert!(children.get(1).is_none());
+        assert_eq!(children.pop_first().map(|(n, _)| n), Some(3));
+        assert_eq!(
+            children.iter().map(|(n, _)| n).collect::<Vec<_>>(),
+            vec![5, 9]
+        );
+    }
+
     #[test]
     fn test_into_boxed() {
         let node: Node<Vec<u8>, Vec<u8>> = Node::Empty;
@@ -0,0 +1,498 @@
+//! Tries holding only the parts revealed by proof nodes.
+//!
+//! A partial trie has the shape of the full trie it was revealed from, but
+//! keeps each subtree whose node was not revealed as its hash. Lookups and
+//! updates on revealed paths behave as on the full trie, including the
+//! collapsing of branches on delete, and yield the same root hash. Updates
+//! that reach an unrevealed subtree fail with [`TrieError::MissingWitness`].
+//!
+//! [`PartialTrie`] builds such a trie from any number of proofs or
+//! witnesses, revealing more of it as nodes arrive.
+//!
+//! Partial tries use the node encoding of [`HashMode::Native`]. Nodes of
+//! Ethereum proofs do not match native hashes, so they reveal nothing and
+//! reads of the trie fail with [`TrieError::MissingWitness`].
+//!
+//! [`HashMode::Native`]: crate::HashMode::Native
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    hash::{
+        decode_node, encode_branch, encode_empty, encode_extension, encode_leaf, hash_data,
+        node_reference, DecodedNode, HASH_LEN,
+    },
+    mutation::{self, NodeBuilder, NodeView, View},
+    utils::{to_nibbles, verify_key},
+    verify::NodeIndex,
+    Result, TrieError,
+};
+
+/// Trie revealed from proof nodes, without the rest of the full trie
+///
+/// Starts out knowing only the root hash. Revealing nodes from proofs or
+/// witnesses fills in the paths they cover, which can then be read and
+/// updated as on the full trie.
+///
+/// # Examples
+/// ```
+/// # use patricia_merkle_trie::{proof::MerkleProofTrait, PartialTrie, PatriciaMerkleTrie, Result};
+/// # fn main() -> Result<()> {
+/// let mut trie = PatriciaMerkleTrie::new();
+/// trie.insert(b"car".to_vec(), vec![1; 40])?;
+/// trie.insert(b"dog".to_vec(), vec![2; 40])?;
+///
+/// let mut partial = PartialTrie::new(trie.root_hash()?);
+/// partial.reveal(&trie.generate_proof(&b"car".to_vec())?.nodes)?;
+/// partial.insert(b"cart", vec![3])?;
+///
+/// trie.insert(b"cart".to_vec(), vec![3])?;
+/// assert_eq!(partial.root_hash()?, trie.root_hash()?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialTrie {
+    root: PartialNode,
+}
+
+impl PartialTrie {
+    /// Creates a partial trie of which only the root hash is known
+    pub fn new(root_hash: Vec<u8>) -> Self {
+        PartialTrie {
+            root: PartialNode::Hash(root_hash),
+        }
+    }
+
+    /// Reveals the nodes below the known part of the trie
+    ///
+    /// Accepts the nodes of any proof, multi-proof, range proof or witness
+    /// for the trie. Nodes that do not belong to an unrevealed subtree, and
+    /// nodes listed more than once, are ignored.
+    ///
+    /// # Arguments
+    /// * `nodes` - Encodings of the nodes to reveal, in any order
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the matching nodes were revealed
+    /// * `Err(TrieError::InvalidProof)` - If a matching node is malformed;
+    ///   the trie is then left unchanged
+    pub fn reveal(&mut self, nodes: &[Vec<u8>]) -> Result<()> {
+        let by_hash: HashMap<_, _> = nodes
+            .iter()
+            .map(|node| (hash_data(node), node.as_slice()))
+            .collect();
+        let mut root = self.root.clone();
+        root.resolve(
+            &mut |hash| by_hash.get(hash).map(|node| node.to_vec()),
+            true,
+        )?;
+        self.root = root;
+        Ok(())
+    }
+
+    /// Looks up the value of a key
+    ///
+    /// # Returns
+    /// * `Ok(Some(&[u8]))` - The value, if the key is present
+    /// * `Ok(None)` - If the revealed nodes show the key is absent
+    /// * `Err(TrieError::MissingWitness)` - If the lookup reaches a subtree
+    ///   that was not revealed
+    /// * `Err(TrieError)` - If the key is invalid
+    pub fn get(&self, key: &[u8]) -> Result<Option<&[u8]>> {
+        verify_key(key)?;
+        self.root.get(&to_nibbles(key)?, 0)
+    }
+
+    /// Inserts or updates the value of a key
+    ///
+    /// # Returns
+    /// * `Ok(())` - On successful insertion
+    /// * `Err(TrieError::MissingWitness)` - If the insertion reaches a
+    ///   subtree that was not revealed; the trie is then left unchanged
+    /// * `Err(TrieError)` - If the key is invalid
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        verify_key(key)?;
+        self.root = self.root.clone().insert(&to_nibbles(key)?, 0, value)?;
+        Ok(())
+    }
+
+    /// Deletes a key
+    ///
+    /// # Returns
+    /// * `Ok(Some(Vec<u8>))` - The removed value, if the key was present
+    /// * `Ok(None)` - If the key was absent
+    /// * `Err(TrieError::MissingWitness)` - If the deletion reaches a
+    ///   subtree that was not revealed, including a sibling that a
+    ///   collapsing branch merges into its parent; the trie is then left
+    ///   unchanged
+    /// * `Err(TrieError)` - If the key is invalid
+    pub fn delete(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        verify_key(key)?;
+        let (root, removed) = self.root.clone().delete(&to_nibbles(key)?, 0)?;
+        self.root = root;
+        Ok(removed)
+    }
+
+    /// Computes the root hash, which matches the full trie after the same
+    /// updates
+    pub fn root_hash(&self) -> Result<Vec<u8>> {
+        self.root.root_hash()
+    }
+}
+
+/// Node of a partial trie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PartialNode {
+    Empty,
+    Leaf {
+        path: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Extension {
+        prefix: Vec<u8>,
+        child: Box<PartialNode>,
+    },
+    Branch {
+        children: BTreeMap<u8, PartialNode>,
+        value: Option<Vec<u8>>,
+    },
+    /// A subtree that was not revealed, known only by its hash
+    Hash(Vec<u8>),
+}
+
+impl PartialNode {
+    /// Reveals the trie below `root_hash` from node encodings
+    ///
+    /// # Arguments
+    /// * `root_hash` - Hash of the root node
+    /// * `nodes` - Encodings of the revealed nodes, in any order
+    ///
+    /// # Returns
+    /// * `Ok(PartialNode)` - The root, with unrevealed children as hashes
+    /// * `Err(TrieError::InvalidProof)` - If the root is not among the nodes,
+    ///   a node is malformed or listed twice, or a node is not below the root
+    pub(crate) fn from_nodes(root_hash: &[u8], nodes: &[Vec<u8>]) -> Result<Self> {
+        let mut index = NodeIndex::new(nodes).map_err(|_| TrieError::InvalidProof)?;
+        let mut root = Self::Hash(root_hash.to_vec());
+        root.resolve(&mut |hash| index.fetch(hash), true)?;
+        if matches!(root, Self::Hash(_)) || !index.all_used() {
+            return Err(TrieError::InvalidProof);
+        }
+        Ok(root)
+    }
+
+    /// Replaces the unrevealed nodes below and including this one with the
+    /// nodes `fetch` returns for their hashes
+    ///
+    /// `fetch` returns `None` for nodes that stay unrevealed.
+    pub(crate) fn resolve<F>(&mut self, fetch: &mut F, is_root: bool) -> Result<()>
+    where
+        F: FnMut(&[u8]) -> Option<Vec<u8>>,
+    {
+        if let Self::Hash(hash) = self {
+            match fetch(hash) {
+                Some(encoded) => *self = Self::decode(&encoded, is_root)?,
+                None => return Ok(()),
+            }
+        }
+        match self {
+            Self::Extension { child, .. } => {
+                child.resolve(fetch, false)?;
+                // Extensions always lead to a branch
+                if !matches!(**child, Self::Branch { .. } | Self::Hash(_)) {
+                    return Err(TrieError::InvalidProof);
+                }
+            }
+            Self::Branch { children, .. } => {
+                for child in children.values_mut() {
+                    child.resolve(fetch, false)?;
+                }
+            }
+            Self::Empty | Self::Leaf { .. } | Self::Hash(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Decodes a node, keeping its hashed children as their hashes
+    fn decode(encoded: &[u8], is_root: bool) -> Result<Self> {
+        let node = match decode_node(encoded)? {
+            DecodedNode::Empty if is_root => Self::Empty,
+            DecodedNode::Empty => return Err(TrieError::InvalidProof),
+            DecodedNode::Leaf { path, value } => Self::Leaf { path, value },
+            DecodedNode::Extension { prefix, child } => Self::Extension {
+                prefix,
+                child: Box::new(Self::decode_child(child)?),
+            },
+            DecodedNode::Branch { children, value } => Self::Branch {
+                children: children
+                    .into_iter()
+                    .map(|(nibble, child)| Ok((nibble, Self::decode_child(child)?)))
+                    .collect::<Result<_>>()?,
+                value,
+            },
+        };
+        Ok(node)
+    }
+
+    /// Decodes an embedded child, or keeps a hashed one as its hash
+    fn decode_child(reference: Vec<u8>) -> Result<Self> {
+        if reference.len() < HASH_LEN {
+            Self::decode(&reference, false)
+        } else {
+            Ok(Self::Hash(reference))
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        matches!(self, Self::Empty)
+    }
+
+    /// Encodes the node
+    ///
+    /// An unrevealed node only has its hash, so it cannot be encoded.
+    fn encode(&self) -> Result<Vec<u8>> {
+        match self {
+            Self::Empty => Ok(encode_empty()),
+            Self::Leaf { path, value } => Ok(encode_leaf(path, value)),
+            Self::Extension { prefix, child } => encode_extension(prefix, &child.reference()?),
+            Self::Branch { children, value } => {
+                let child_refs = children
+                    .iter()
+                    .map(|(nibble, child)| Ok((*nibble, child.reference()?)))
+                    .collect::<Result<Vec<_>>>()?;
+                encode_branch(&child_refs, value.as_deref())
+            }
+            Self::Hash(_) => Err(TrieError::InvalidNodeType),
+        }
+    }
+
+    /// Computes the reference to the node from its parent
+    fn reference(&self) -> Result<Vec<u8>> {
+        match self {
+            Self::Hash(hash) => Ok(hash.clone()),
+            node => Ok(node_reference(node.encode()?)),
+        }
+    }
+
+    /// Computes the hash of the node as the root of a trie
+    pub(crate) fn root_hash(&self) -> Result<Vec<u8>> {
+        match self {
+            Self::Hash(hash) => Ok(hash.clone()),
+            node => Ok(hash_data(&node.encode()?)),
+        }
+    }
+
+    /// Looks up the value of `key` below the node at `depth` nibbles
+    pub(crate) fn get(&self, key: &[u8], depth: usize) -> Result<Option<&[u8]>> {
+        let nibbles = &key[depth..];
+        match self {
+            Self::Empty => Ok(None),
+            Self::Leaf { path, value } => Ok((path[..] == *nibbles).then_some(value.as_slice())),
+            Self::Extension { prefix, child } => {
+                if !nibbles.starts_with(prefix) {
+                    return Ok(None);
+                }
+                child.get(key, depth + prefix.len())
+            }
+            Self::Branch { children, value } => match nibbles.first() {
+                None => Ok(value.as_deref()),
+                Some(nibble) => match children.get(nibble) {
+                    Some(child) => child.get(key, depth + 1),
+                    None => Ok(None),
+                },
+            },
+            Self::Hash(_) => Err(TrieError::MissingWitness(key[..depth].to_vec())),
+        }
+    }
+
+    /// Inserts `value` under `key` below the node at `depth` nibbles
+    ///
+    /// Shares the insertion of the full trie, so both end up with the
+    /// same shape.
+    pub(crate) fn insert(self, key: &[u8], depth: usize, value: Vec<u8>) -> Result<Self> {
+        mutation::insert(&mut Builder, self, key, depth, value)
+    }
+
+    /// Deletes `key` below the node at `depth` nibbles
+    ///
+    /// # Returns
+    /// The node after the deletion and the deleted value, if any
+    pub(crate) fn delete(self, key: &[u8], depth: usize) -> Result<(Self, Option<Vec<u8>>)> {
+        mutation::delete(&mut Builder, self, key, depth)
+    }
+}
+
+/// Builds the nodes of partial tries, which are not recorded anywhere
+struct Builder;
+
+impl NodeBuilder for Builder {
+    type Node = PartialNode;
+    type Value = Vec<u8>;
+    type Children = BTreeMap<u8, PartialNode>;
+
+    fn open(node: PartialNode) -> Result<View<Self>> {
+        Ok(match node {
+            PartialNode::Empty => NodeView::Empty,
+            PartialNode::Leaf { path, value } => NodeView::Leaf { path, value },
+            PartialNode::Extension { prefix, child } => NodeView::Extension { prefix, child },
+            PartialNode::Branch { children, value } => NodeView::Branch { children, value },
+            hash @ PartialNode::Hash(_) => NodeView::Unrevealed(hash),
+        })
+    }
+
+    fn close(view: View<Self>) -> PartialNode {
+        match view {
+            NodeView::Empty => PartialNode::Empty,
+            NodeView::Leaf { path, value } => PartialNode::Leaf { path, value },
+            NodeView::Extension { prefix, child } => PartialNode::Extension { prefix, child },
+            NodeView::Branch { children, value } => PartialNode::Branch { children, value },
+            NodeView::Unrevealed(node) => node,
+        }
+    }
+
+    fn store(&mut self, node: PartialNode) -> Result<PartialNode> {
+        Ok(node)
+    }
+
+    fn is_empty(node: &PartialNode) -> bool {
+        node.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{ExtendedProofTrait, MerkleProofTrait},
+        witness::{Operation, Witness},
+        PatriciaMerkleTrie,
+    };
+
+    type Trie = PatriciaMerkleTrie<Vec<u8>, Vec<u8>>;
+
+    fn build(entries: &[(&[u8], usize)]) -> Result<Trie> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for (key, len) in entries {
+            trie.insert(key.to_vec(), vec![key[0]; *len])?;
+        }
+        Ok(trie)
+    }
+
+    fn revealed(trie: &Trie, keys: &[&[u8]]) -> Result<PartialTrie> {
+        let keys: Vec<_> = keys.iter().map(|key| key.to_vec()).collect();
+        let mut partial = PartialTrie::new(trie.root_hash()?);
+        partial.reveal(&trie.generate_multi_proof(&keys)?.nodes)?;
+        Ok(partial)
+    }
+
+    #[test]
+    fn test_partial_updates_match_full_trie() -> Result<()> {
+        let mut trie = build(&[(b"car", 40), (b"cart", 40), (b"dog", 40), (b"a", 2)])?;
+        let mut partial = revealed(&trie, &[b"car", b"cart", b"cat", b"dog"])?;
+        assert_eq!(partial.root_hash()?, trie.root_hash()?);
+        assert_eq!(partial.get(b"car")?, Some(&[b'c'; 40][..]));
+        assert_eq!(partial.get(b"cat")?, None);
+
+        partial.insert(b"cat", vec![1; 40])?;
+        trie.insert(b"cat".to_vec(), vec![1; 40])?;
+        assert_eq!(partial.root_hash()?, trie.root_hash()?);
+
+        partial.insert(b"ca", vec![2])?;
+        trie.insert(b"ca".to_vec(), vec![2])?;
+        assert_eq!(partial.root_hash()?, trie.root_hash()?);
+
+        assert_eq!(partial.delete(b"car")?, Some(vec![b'c'; 40]));
+        trie.delete(&b"car".to_vec())?;
+        assert_eq!(partial.root_hash()?, trie.root_hash()?);
+        assert_eq!(partial.delete(b"car")?, None);
+        assert_eq!(partial.get(b"ca")?, Some(&[2][..]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unrevealed_subtrees_are_missing() -> Result<()> {
+        let mut trie = build(&[(b"car", 40), (b"cart", 40), (b"dog", 40)])?;
+        let partial = PartialTrie::new(trie.root_hash()?);
+        assert!(matches!(
+            partial.get(b"car"),
+            Err(TrieError::MissingWitness(path)) if path.is_empty()
+        ));
+
+        let mut partial = revealed(&trie, &[b"dog"])?;
+        let before = partial.clone();
+        assert!(matches!(
+            partial.insert(b"cab", vec![1]),
+            Err(TrieError::MissingWitness(path)) if path == b"c"
+        ));
+
+        // Deleting "dog" merges the unrevealed "car" subtree into the root
+        assert!(matches!(
+            partial.delete(b"dog"),
+            Err(TrieError::MissingWitness(_))
+        ));
+        assert_eq!(partial, before);
+
+        partial.reveal(&trie.generate_proof(&b"car".to_vec())?.nodes)?;
+        partial.delete(b"dog")?;
+        trie.delete(&b"dog".to_vec())?;
+        assert_eq!(partial.root_hash()?, trie.root_hash()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reveal_ignores_unrelated_nodes() -> Result<()> {
+        let trie = build(&[(b"car", 40), (b"dog", 40)])?;
+        let other = build(&[(b"cow", 40), (b"dig", 40)])?;
+        let mut partial = PartialTrie::new(trie.root_hash()?);
+
+        // Nodes of another trie, and nodes listed twice, change nothing
+        partial.reveal(&other.generate_proof(&b"cow".to_vec())?.nodes)?;
+        assert_eq!(partial, PartialTrie::new(trie.root_hash()?));
+        let nodes = trie.generate_proof(&b"car".to_vec())?.nodes;
+        partial.reveal(&[nodes.clone(), nodes].concat())?;
+        assert_eq!(partial.get(b"car")?, Some(&[b'c'; 40][..]));
+
+        // A malformed node under a matching hash is rejected
+        let malformed = vec![0x05; 40];
+        let mut forged = PartialTrie::new(hash_data(&malformed));
+        assert!(forged.reveal(&[malformed]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ethereum_proofs_reveal_nothing() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::with_mode(crate::HashMode::Ethereum);
+        trie.insert(b"car".to_vec(), vec![1; 40])?;
+        trie.insert(b"dog".to_vec(), vec![2; 40])?;
+
+        let mut partial = PartialTrie::new(trie.root_hash()?);
+        partial.reveal(&trie.generate_eth_proof(&b"car".to_vec())?)?;
+        assert!(matches!(
+            partial.get(b"car"),
+            Err(TrieError::MissingWitness(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reveal_from_witness_and_empty_trie() -> Result<()> {
+        let mut trie = build(&[(b"car", 40), (b"cart", 40), (b"dog", 40)])?;
+        let Witness { nodes, .. } =
+            trie.generate_witness(vec![Operation::Delete(b"dog".to_vec())])?;
+        let mut partial = PartialTrie::new(trie.root_hash()?);
+        partial.reveal(&nodes)?;
+        partial.delete(b"dog")?;
+        trie.delete(&b"dog".to_vec())?;
+        assert_eq!(partial.root_hash()?, trie.root_hash()?);
+
+        let empty = Trie::new();
+        let mut partial = PartialTrie::new(empty.root_hash()?);
+        partial.reveal(&empty.generate_exclusion_proof(&b"a".to_vec())?.nodes)?;
+        assert_eq!(partial.get(b"a")?, None);
+        partial.insert(b"a", vec![1])?;
+        assert_eq!(partial.delete(b"a")?, Some(vec![1]));
+        assert_eq!(partial.root_hash()?, empty.root_hash()?);
+        Ok(())
+    }
+}
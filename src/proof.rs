@@ -1,16 +1,76 @@
+//! Merkle proof generation and verification.
+//!
+//! A proof for a key lists the encodings of the nodes on the path from the
+//! root to the key, starting at the root. Each branch encoding carries the
+//! references to all of its children, so the siblings of the path, and its
+//! value, and each extension carries its prefix. Nodes embedded in their
+//! parent are part of the parent's encoding and are not listed separately.
+//!
+//! The verifier hashes the first node and compares it with the root hash,
+//! then follows the key through the decoded nodes, checking that each
+//! listed node hashes to the reference its parent holds, until it reaches
+//! the value.
+//!
+//! A key that is a prefix of another key, such as `a` next to `ab`, keeps
+//! its value on the branch where the two paths part. Its proof ends at that
+//! branch, whose encoding carries the value, and an exclusion proof for a
+//! prefix ends at a branch without a value.
+//!
+//! A [`MultiProof`] covers several keys at once. It lists each node needed
+//! by any of the keys once, in no particular order, and the verifier looks
+//! nodes up by their hash instead of taking them in path order.
+//!
+//! A [`RangeProof`] lists every node whose subtree can hold keys in the
+//! range. The verifier descends into each such subtree, so a key left out
+//! of the proven entries would need a node that is not in the proof.
+//!
+//! A [`PrefixProof`] works the same way for every key starting with a
+//! prefix. It reveals the whole subtree below the node where the prefix
+//! ends, along with the path to that node, so it also shows that no other
+//! key under the prefix exists.
+//!
+//! Proofs for a single present key come from [`MerkleProofTrait`], and
+//! exclusion, multi-key, range and prefix proofs from [`ExtendedProofTrait`].
+//!
+//! Proofs use the node encoding of [`HashMode::Native`]. Generating one
+//! for a trie in [`HashMode::Ethereum`] fails with
+//! [`TrieError::UnsupportedHashMode`], as it could not verify against the
+//! Ethereum root; such tries are proven with
+//! [`PatriciaMerkleTrie::generate_eth_proof`] instead.
+//!
+//! [`MerkleProof::to_bytes`] and the `to_bytes` methods of the other proof
+//! types give a proof a compact binary form to send to other processes. The
+//! form starts with [`PROOF_FORMAT_VERSION`], which `from_bytes` checks.
+//! With the `serde` feature the proof types also implement `Serialize` and
+//! `Deserialize`.
+//!
+//! [`MerkleProofTrait`]: crate::proof::MerkleProofTrait
+//! [`ExtendedProofTrait`]: crate::proof::ExtendedProofTrait
+//! [`MultiProof`]: crate::proof::MultiProof
+//! [`RangeProof`]: crate::proof::RangeProof
+//! [`PrefixProof`]: crate::proof::PrefixProof
+//! [`MerkleProof::to_bytes`]: crate::proof::MerkleProof::to_bytes
+//! [`PROOF_FORMAT_VERSION`]: crate::proof::PROOF_FORMAT_VERSION
+//! [`HashMode::Native`]: crate::HashMode::Native
+//! [`HashMode::Ethereum`]: crate::HashMode::Ethereum
+//! [`TrieError::UnsupportedHashMode`]: crate::TrieError::UnsupportedHashMode
+//! [`PatriciaMerkleTrie::generate_eth_proof`]: crate::PatriciaMerkleTrie::generate_eth_proof
+
+use std::collections::HashSet;
+
 use crate::{
-    hash::{hash_branch, hash_empty, hash_leaf},
+    hash::{hash_data, HASH_LEN},
     node::Node,
-    utils::{common_prefix, to_nibbles},
-    PatriciaMerkleTrie, Result, TrieError,
+    utils::to_nibbles,
+    verify::{self, KeySet},
+    HashMode, PatriciaMerkleTrie, Result, TrieError,
 };
 
-#[derive(Debug, Clone)]
-pub struct MerkleProof {
-    pub key: Vec<u8>,
-    pub value: Vec<u8>,
-    pub proof: Vec<(u8, Vec<u8>)>,
-}
+pub use crate::verify::{
+    check_exclusion_proof, check_multi_proof, check_prefix_proof, check_proof, check_range_proof,
+    ExclusionProof, MerkleProof, MultiProof, PrefixProof, ProofError, RangeProof,
+    PROOF_FORMAT_VERSION,
+};
 
 pub trait MerkleProofTrait<K, V> {
     fn generate_proof(&self, key: &K) -> Result<MerkleProof>;
@@ -18,9 +78,58 @@ pub trait MerkleProofTrait<K, V> {
     fn hash_node(&self, node: &Node<K, Option<V>>) -> Result<Vec<u8>>;
 }
 
-// Type aliases for complex types
-type Proof = Vec<(u8, Vec<u8>)>;
-type ProofResult = Result<(Vec<u8>, Proof)>;
+/// Proofs about the absence of a key and about sets of keys.
+///
+/// Kept apart from [`MerkleProofTrait`] so that implementors of that trait
+/// are not required to support these proofs. The verifying methods only
+/// need the proof and have default implementations.
+///
+/// The `verify_*` methods return `Ok(false)` for a proof that does not hold.
+/// The `check_*` methods, including [`check_proof`](Self::check_proof) for
+/// a [`MerkleProof`], fail with [`TrieError::ProofFailed`] instead, naming
+/// the step at which verification failed.
+pub trait ExtendedProofTrait<K> {
+    fn generate_exclusion_proof(&self, key: &K) -> Result<ExclusionProof>;
+    fn generate_multi_proof(&self, keys: &[K]) -> Result<MultiProof>;
+    fn generate_range_proof(&self, start: &K, end: &K) -> Result<RangeProof>;
+    fn generate_prefix_proof(&self, prefix: &K) -> Result<PrefixProof>;
+
+    fn verify_exclusion_proof(root_hash: Vec<u8>, proof: ExclusionProof) -> Result<bool> {
+        Ok(verify::verify_exclusion_proof(&root_hash, &proof)?)
+    }
+
+    fn verify_multi_proof(root_hash: Vec<u8>, proof: MultiProof) -> Result<bool> {
+        Ok(verify::verify_multi_proof(&root_hash, &proof)?)
+    }
+
+    fn verify_range_proof(root_hash: Vec<u8>, proof: RangeProof) -> Result<bool> {
+        Ok(verify::verify_range_proof(&root_hash, &proof)?)
+    }
+
+    fn verify_prefix_proof(root_hash: Vec<u8>, proof: PrefixProof) -> Result<bool> {
+        Ok(verify::verify_prefix_proof(&root_hash, &proof)?)
+    }
+
+    fn check_proof(root_hash: Vec<u8>, proof: MerkleProof) -> Result<()> {
+        Ok(verify::check_proof(&root_hash, &proof)?)
+    }
+
+    fn check_exclusion_proof(root_hash: Vec<u8>, proof: ExclusionProof) -> Result<()> {
+        Ok(verify::check_exclusion_proof(&root_hash, &proof)?)
+    }
+
+    fn check_multi_proof(root_hash: Vec<u8>, proof: MultiProof) -> Result<()> {
+        Ok(verify::check_multi_proof(&root_hash, &proof)?)
+    }
+
+    fn check_range_proof(root_hash: Vec<u8>, proof: RangeProof) -> Result<()> {
+        Ok(verify::check_range_proof(&root_hash, &proof)?)
+    }
+
+    fn check_prefix_proof(root_hash: Vec<u8>, proof: PrefixProof) -> Result<()> {
+        Ok(verify::check_prefix_proof(&root_hash, &proof)?)
+    }
+}
 
 impl<K, V> MerkleProofTrait<K, V> for PatriciaMerkleTrie<K, V>
 where
@@ -28,91 +137,112 @@ where
     V: Clone + AsRef<[u8]> + std::fmt::Debug,
 {
     fn generate_proof(&self, key: &K) -> Result<MerkleProof> {
-        // Validate key
-        if key.as_ref().is_empty() {
-            return Err(TrieError::InvalidKey);
-        }
+        self.require_mode(HashMode::Native)?;
+        // Return error if no value is stored under the key
+        let value = self.get(key)?.ok_or(TrieError::NodeNotFound)?;
 
         let key_nibbles = to_nibbles(key.as_ref())?;
-        let (value, proof) = self.generate_proof_at(self.root(), key_nibbles, vec![])?;
-
-        // Return error if proof is empty (key not found) or no value was found
-        if proof.is_empty() || value.is_empty() {
-            return Err(TrieError::NodeNotFound);
-        }
+        let mut nodes = Vec::new();
+        self.collect_proof_nodes(self.root(), &key_nibbles, true, &mut nodes)?;
 
         Ok(MerkleProof {
             key: key.as_ref().to_vec(),
-            value,
-            proof,
+            value: value.as_ref().to_vec(),
+            nodes,
         })
     }
 
     fn verify_proof(root_hash: Vec<u8>, proof: MerkleProof) -> Result<bool> {
-        // Validate inputs
-        if proof.key.is_empty() {
-            return Err(TrieError::InvalidKey);
-        }
-        if proof.proof.is_empty() {
-            return Err(TrieError::InvalidProof);
+        Ok(verify::verify_proof(&root_hash, &proof)?)
+    }
+
+    fn hash_node(&self, node: &Node<K, Option<V>>) -> Result<Vec<u8>> {
+        PatriciaMerkleTrie::hash_node(self, node)
+    }
+}
+
+impl<K, V> ExtendedProofTrait<K> for PatriciaMerkleTrie<K, V>
+where
+    K: AsRef<[u8]> + Clone + From<Vec<u8>> + std::fmt::Debug,
+    V: Clone + AsRef<[u8]> + std::fmt::Debug,
+{
+    fn generate_exclusion_proof(&self, key: &K) -> Result<ExclusionProof> {
+        self.require_mode(HashMode::Native)?;
+        // Return error if a value is stored under the key
+        if self.get(key)?.is_some() {
+            return Err(TrieError::KeyExists);
         }
 
-        // Start with the leaf hash
-        let mut current_hash = hash_leaf(&proof.key, &proof.value)?;
+        let key_nibbles = to_nibbles(key.as_ref())?;
+        let mut nodes = Vec::new();
+        self.collect_proof_nodes(self.root(), &key_nibbles, true, &mut nodes)?;
 
-        // Process proof elements from leaf to root
-        let mut proof_iter = proof.proof.iter().peekable();
+        Ok(ExclusionProof {
+            key: key.as_ref().to_vec(),
+            nodes,
+        })
+    }
 
-        while let Some((nibble, hash)) = proof_iter.next() {
-            if hash.is_empty() {
-                return Err(TrieError::InvalidProof);
-            }
+    fn generate_multi_proof(&self, keys: &[K]) -> Result<MultiProof> {
+        self.require_mode(HashMode::Native)?;
+        // A proof for no keys would prove nothing
+        if keys.is_empty() {
+            return Err(TrieError::InvalidKey);
+        }
+        let mut values = Vec::with_capacity(keys.len());
+        let mut nodes = Vec::new();
+        let mut seen = HashSet::new();
 
-            // First verify that the current hash matches
-            if current_hash != *hash {
-                return Ok(false);
-            }
+        for key in keys {
+            values.push(self.get(key)?.map(|value| value.as_ref().to_vec()));
 
-            // If there's a next hash, prepare the current_hash for it
-            if let Some((next_nibble, _)) = proof_iter.peek() {
-                if *next_nibble == 0 {
-                    // Next is a branch node, compute branch hash with current as child
-                    let children = vec![(*nibble, current_hash)];
-                    current_hash = hash_branch(&[], &children, &[])?;
-                } else {
-                    // Next is another child node, just take its hash
-                    current_hash = hash.clone();
+            let key_nibbles = to_nibbles(key.as_ref())?;
+            let mut path = Vec::new();
+            self.collect_proof_nodes(self.root(), &key_nibbles, true, &mut path)?;
+            // Keep only the first occurrence of nodes shared with earlier keys
+            for node in path {
+                if seen.insert(hash_data(&node)) {
+                    nodes.push(node);
                 }
             }
         }
 
-        Ok(current_hash == root_hash)
+        Ok(MultiProof {
+            keys: keys.iter().map(|key| key.as_ref().to_vec()).collect(),
+            values,
+            nodes,
+        })
     }
 
-    fn hash_node(&self, node: &Node<K, Option<V>>) -> Result<Vec<u8>> {
-        match node {
-            Node::Empty => Ok(hash_empty()),
-            Node::Leaf { key, value } => hash_leaf(
-                key.as_ref(),
-                value.as_ref().map(|v| v.as_ref()).unwrap_or(&[]),
-            ),
-            Node::Branch {
-                prefix,
-                children,
-                value,
-            } => {
-                let child_hashes = children
-                    .iter()
-                    .map(|(k, child)| Ok((*k, self.hash_node(child)?)))
-                    .collect::<Result<Vec<_>>>()?;
-
-                hash_branch(
-                    prefix.as_ref(),
-                    &child_hashes,
-                    value.as_ref().map(|v| v.as_ref()).unwrap_or(&[]),
-                )
-            }
+    fn generate_range_proof(&self, start: &K, end: &K) -> Result<RangeProof> {
+        self.require_mode(HashMode::Native)?;
+        let start = to_nibbles(start.as_ref())?;
+        let end = to_nibbles(end.as_ref())?;
+        if start > end {
+            return Err(TrieError::InvalidKey);
         }
+
+        let (entries, nodes) = self.collect_key_set(KeySet::Range {
+            start: &start,
+            end: &end,
+        })?;
+        Ok(RangeProof {
+            start,
+            end,
+            entries,
+            nodes,
+        })
+    }
+
+    fn generate_prefix_proof(&self, prefix: &K) -> Result<PrefixProof> {
+        self.require_mode(HashMode::Native)?;
+        let prefix = to_nibbles(prefix.as_ref())?;
+        let (entries, nodes) = self.collect_key_set(KeySet::Prefix(&prefix))?;
+        Ok(PrefixProof {
+            prefix,
+            entries,
+            nodes,
+        })
     }
 }
 
@@ -121,83 +251,802 @@ where
     K: AsRef<[u8]> + Clone + From<Vec<u8>> + std::fmt::Debug,
     V: Clone + AsRef<[u8]> + std::fmt::Debug,
 {
-    fn generate_proof_at(
+    /// Collects the encodings of the nodes on the path to `nibbles`
+    ///
+    /// The path is followed as far as the trie goes, so it also ends early
+    /// for keys that are not in the trie. Embedded nodes are skipped, as
+    /// they are part of their parent's encoding.
+    pub(crate) fn collect_proof_nodes(
         &self,
         node: &Node<K, Option<V>>,
-        nibbles: Vec<u8>,
-        mut proof: Proof,
-    ) -> ProofResult {
-        let node_hash = self.hash_node(node)?;
+        nibbles: &[u8],
+        is_root: bool,
+        nodes: &mut Vec<Vec<u8>>,
+    ) -> Result<()> {
+        let encoded = self.encode_node(node)?;
+        if is_root || encoded.len() >= HASH_LEN {
+            nodes.push(encoded);
+        }
 
         match node {
-            Node::Empty => Ok((vec![], proof)),
-            Node::Leaf { key, value } => {
-                let existing_nibbles = to_nibbles(key.as_ref())?;
-                if nibbles != existing_nibbles {
-                    return Ok((vec![], proof));
+            Node::Empty | Node::Leaf { .. } => Ok(()),
+            Node::Extension { prefix, child } => {
+                let prefix_nibbles = to_nibbles(prefix.as_ref())?;
+                match nibbles.strip_prefix(prefix_nibbles.as_slice()) {
+                    Some(rest) => self.collect_proof_nodes(child, rest, false, nodes),
+                    None => Ok(()),
                 }
+            }
+            Node::Branch { children, .. } => match nibbles.split_first() {
+                Some((nibble, rest)) => match children.get(*nibble) {
+                    Some(child) => self.collect_proof_nodes(child, rest, false, nodes),
+                    None => Ok(()),
+                },
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// Collects the entries in a set of keys, along with the encodings of
+    /// the nodes whose subtrees can hold keys in the set
+    fn collect_key_set(&self, keys: KeySet) -> Result<KeySetEntries> {
+        let mut range = RangeCollector {
+            keys,
+            seen: HashSet::new(),
+            nodes: Vec::new(),
+            entries: Vec::new(),
+        };
+        self.collect_range_nodes(self.root(), Vec::new(), true, &mut range)?;
+        Ok((range.entries, range.nodes))
+    }
 
-                let value_bytes = value
-                    .as_ref()
-                    .map(|v| v.as_ref().to_vec())
-                    .unwrap_or_default();
+    /// Collects the encodings of the nodes whose subtrees overlap a set of
+    /// keys, along with the entries in the set
+    ///
+    /// Nodes with identical encodings are collected once, as are embedded
+    /// nodes skipped.
+    fn collect_range_nodes(
+        &self,
+        node: &Node<K, Option<V>>,
+        mut path: Vec<u8>,
+        is_root: bool,
+        range: &mut RangeCollector,
+    ) -> Result<()> {
+        let encoded = self.encode_node(node)?;
+        if (is_root || encoded.len() >= HASH_LEN) && range.seen.insert(hash_data(&encoded)) {
+            range.nodes.push(encoded);
+        }
 
-                // Add leaf hash to proof
-                proof.push((existing_nibbles[0], node_hash));
-                Ok((value_bytes, proof))
-            }
-            Node::Branch {
-                prefix,
-                children,
+        match node {
+            Node::Empty => {}
+            Node::Leaf {
+                path: suffix,
                 value,
             } => {
-                let prefix_nibbles = to_nibbles(prefix.as_ref())?;
-                let common_len = common_prefix(&prefix_nibbles, &nibbles);
-
-                // If we don't match the entire prefix, key is not in this branch
-                if common_len < prefix_nibbles.len() {
-                    return Ok((vec![], proof));
+                path.extend_from_slice(suffix.as_ref());
+                if let Some(value) = value {
+                    if range.keys.contains(&path) {
+                        range.entries.push((path, value.as_ref().to_vec()));
+                    }
+                }
+            }
+            Node::Extension { prefix, child } => {
+                path.extend_from_slice(prefix.as_ref());
+                if range.keys.overlaps(&path) {
+                    self.collect_range_nodes(child, path, false, range)?;
                 }
+            }
+            Node::Branch { children, value } => {
+                if let Some(value) = value {
+                    if range.keys.contains(&path) {
+                        range.entries.push((path.clone(), value.as_ref().to_vec()));
+                    }
+                }
+                for (nibble, child) in children.iter() {
+                    let mut child_path = path.clone();
+                    child_path.push(nibble);
+                    if range.keys.overlaps(&child_path) {
+                        self.collect_range_nodes(child, child_path, false, range)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
 
-                let remaining_nibbles = nibbles[common_len..].to_vec();
+/// Entries and node encodings collected for a set of keys
+type KeySetEntries = (Vec<(Vec<u8>, Vec<u8>)>, Vec<Vec<u8>>);
 
-                // If no remaining nibbles, we're at the target branch
-                if remaining_nibbles.is_empty() {
-                    let value_bytes = value
-                        .as_ref()
-                        .map(|v| v.as_ref().to_vec())
-                        .unwrap_or_default();
+/// State of a range or prefix proof being generated
+struct RangeCollector<'a> {
+    keys: KeySet<'a>,
+    /// Hashes of the nodes collected so far
+    seen: HashSet<Vec<u8>>,
+    nodes: Vec<Vec<u8>>,
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
 
-                    if !value_bytes.is_empty() {
-                        // Add branch hash to proof
-                        proof.push((0, node_hash));
-                        return Ok((value_bytes, proof));
-                    }
-                    return Ok((vec![], proof));
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{decode_node, DecodedNode};
 
-                // Otherwise, traverse down the appropriate child
-                let child_nibble = remaining_nibbles[0];
-                if let Some(child) = children.get(&child_nibble) {
-                    let (value_bytes, child_proof) =
-                        self.generate_proof_at(child, remaining_nibbles[1..].to_vec(), vec![])?;
+    type Trie = PatriciaMerkleTrie<Vec<u8>, Vec<u8>>;
 
-                    // Only proceed if we found a value
-                    if !value_bytes.is_empty() {
-                        // Add branch hash to proof
-                        proof.push((0, node_hash));
+    fn build(keys: &[&[u8]]) -> Result<Trie> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for key in keys {
+            trie.insert(key.to_vec(), [b"value of ", *key].concat())?;
+        }
+        Ok(trie)
+    }
 
-                        // Add child hash to proof
-                        let child_hash = self.hash_node(child)?;
-                        proof.push((child_nibble, child_hash));
+    #[test]
+    fn test_proofs_in_multi_child_trie() -> Result<()> {
+        let keys: &[&[u8]] = &[b"a", b"b", b"c", b"car", b"cart", b"dog", b"doge"];
+        let trie = build(keys)?;
+        let root = trie.root_hash()?;
 
-                        // Add child proof
-                        proof.extend(child_proof);
-                        return Ok((value_bytes, proof));
-                    }
-                }
-                Ok((vec![], proof))
-            }
+        for key in keys {
+            let proof = trie.generate_proof(&key.to_vec())?;
+            assert_eq!(proof.value, [b"value of ", *key].concat());
+            assert!(Trie::verify_proof(root.clone(), proof)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_values_use_hashed_nodes() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..16u8 {
+            trie.insert(vec![i, i], vec![i; 40])?;
+        }
+        let proof = trie.generate_proof(&vec![3, 3])?;
+        // The root branch and the hashed leaf
+        assert_eq!(proof.nodes.len(), 2);
+        assert!(Trie::verify_proof(trie.root_hash()?, proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampered_proofs_rejected() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..8u8 {
+            trie.insert(vec![1, i], vec![i; 40])?;
+        }
+        let root = trie.root_hash()?;
+        let proof = trie.generate_proof(&vec![1, 5])?;
+
+        // Wrong value for the key
+        let mut wrong_value = proof.clone();
+        wrong_value.value = vec![6; 40];
+        assert!(!Trie::verify_proof(root.clone(), wrong_value)?);
+
+        // Another key whose path goes elsewhere
+        let mut wrong_key = proof.clone();
+        wrong_key.key = vec![1, 6];
+        assert!(!Trie::verify_proof(root.clone(), wrong_key)?);
+
+        // Modified sibling reference in the branch
+        let mut wrong_sibling = proof.clone();
+        let last = wrong_sibling.nodes[1].len() - 2;
+        wrong_sibling.nodes[1][last] ^= 1;
+        assert!(!Trie::verify_proof(root.clone(), wrong_sibling)?);
+
+        // Missing, extra and no nodes
+        let mut missing = proof.clone();
+        missing.nodes.pop();
+        assert!(Trie::verify_proof(root.clone(), missing).is_err());
+        let mut extra = proof.clone();
+        extra.nodes.push(proof.nodes[0].clone());
+        assert!(Trie::verify_proof(root.clone(), extra).is_err());
+        let mut empty = proof;
+        empty.nodes.clear();
+        assert!(Trie::verify_proof(root, empty).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_exclusion_proofs() -> Result<()> {
+        let keys: &[&[u8]] = &[b"a", b"car", b"cart", b"dog", b"doge"];
+        let trie = build(keys)?;
+        let root = trie.root_hash()?;
+
+        // Missing branch child, diverging extension, other leaf, prefix of
+        // a key, and key running past a leaf
+        for key in [&b"b"[..], b"cat", b"do", b"dot", b"ab", b"", b"carts"] {
+            let proof = trie.generate_exclusion_proof(&key.to_vec())?;
+            assert!(Trie::verify_exclusion_proof(root.clone(), proof)?);
+        }
+
+        // Present keys have no exclusion proof
+        assert!(matches!(
+            trie.generate_exclusion_proof(&b"car".to_vec()),
+            Err(TrieError::KeyExists)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_exclusion_proof_cannot_hide_keys() -> Result<()> {
+        let trie = build(&[b"car", b"cart", b"dog"])?;
+        let root = trie.root_hash()?;
+
+        // The path of a present key leads to its value
+        let proof = trie.generate_proof(&b"cart".to_vec())?;
+        let claim = ExclusionProof {
+            key: proof.key,
+            nodes: proof.nodes,
+        };
+        assert!(!Trie::verify_exclusion_proof(root.clone(), claim)?);
+
+        // A proof for one absent key says nothing about a present one
+        let mut proof = trie.generate_exclusion_proof(&b"cat".to_vec())?;
+        proof.key = b"car".to_vec();
+        assert!(!matches!(
+            Trie::verify_exclusion_proof(root.clone(), proof),
+            Ok(true)
+        ));
+
+        // Nor does a proof against another root
+        let proof = trie.generate_exclusion_proof(&b"cat".to_vec())?;
+        assert!(!Trie::verify_exclusion_proof(vec![0; 32], proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_exclusion_proof_in_empty_trie() -> Result<()> {
+        let trie = Trie::new();
+        let proof = trie.generate_exclusion_proof(&b"key".to_vec())?;
+        assert_eq!(proof.nodes.len(), 1);
+        assert!(Trie::verify_exclusion_proof(trie.root_hash()?, proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_proof() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..8u8 {
+            trie.insert(vec![1, i], vec![i; 40])?;
+        }
+        let root = trie.root_hash()?;
+        let keys = vec![vec![1, 2], vec![1, 5], vec![1, 9], vec![2]];
+        let proof = trie.generate_multi_proof(&keys)?;
+
+        assert_eq!(
+            proof.values,
+            vec![Some(vec![2; 40]), Some(vec![5; 40]), None, None]
+        );
+        // The root extension and the branch are shared, the leaves are not
+        let separate: usize = [vec![1, 2], vec![1, 5]]
+            .iter()
+            .map(|key| Ok(trie.generate_proof(key)?.nodes.len()))
+            .sum::<Result<usize>>()?;
+        assert_eq!(proof.nodes.len(), 4);
+        assert_eq!(separate, 6);
+        assert!(Trie::verify_multi_proof(root, proof)?);
+
+        assert!(matches!(
+            trie.generate_multi_proof(&[]),
+            Err(TrieError::InvalidKey)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampered_multi_proofs_rejected() -> Result<()> {
+        let trie = build(&[b"a", b"car", b"cart", b"dog", b"doge"])?;
+        let root = trie.root_hash()?;
+        let keys = vec![b"car".to_vec(), b"cat".to_vec(), b"doge".to_vec()];
+        let proof = trie.generate_multi_proof(&keys)?;
+        assert!(Trie::verify_multi_proof(root.clone(), proof.clone())?);
+
+        // Wrong value, absent key claimed present, present key claimed absent
+        let mut wrong_value = proof.clone();
+        wrong_value.values[0] = Some(b"other".to_vec());
+        assert!(!Trie::verify_multi_proof(root.clone(), wrong_value)?);
+        let mut claimed = proof.clone();
+        claimed.values[1] = Some(b"value of cat".to_vec());
+        assert!(!Trie::verify_multi_proof(root.clone(), claimed)?);
+        let mut hidden = proof.clone();
+        hidden.values[2] = None;
+        assert!(!Trie::verify_multi_proof(root.clone(), hidden)?);
+
+        // Another root
+        assert!(!Trie::verify_multi_proof(vec![0; 32], proof.clone())?);
+
+        // Duplicate, unused and no nodes, and mismatched values
+        let mut duplicate = proof.clone();
+        duplicate.nodes.push(proof.nodes[0].clone());
+        assert!(Trie::verify_multi_proof(root.clone(), duplicate).is_err());
+        let mut unused = proof.clone();
+        unused.keys.pop();
+        unused.values.pop();
+        assert!(Trie::verify_multi_proof(root.clone(), unused).is_err());
+        let mut empty = proof.clone();
+        empty.nodes.clear();
+        assert!(!matches!(
+            Trie::verify_multi_proof(root.clone(), empty),
+            Ok(true)
+        ));
+        let mut short = proof;
+        short.values.pop();
+        assert!(Trie::verify_multi_proof(root, short).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_proofs() -> Result<()> {
+        let keys: &[&[u8]] = &[b"a", b"ab", b"b", b"car", b"cart", b"dog", b"doge"];
+        let trie = build(keys)?;
+        let root = trie.root_hash()?;
+
+        let ranges: &[(&[u8], &[u8])] = &[
+            (b"", b"z"),
+            (b"ab", b"cart"),
+            (b"b", b"c"),
+            (b"ca", b"cb"),
+            (b"cat", b"do"),
+            (b"dog", b"dog"),
+            (b"e", b"f"),
+        ];
+        for (start, end) in ranges {
+            let proof = trie.generate_range_proof(&start.to_vec(), &end.to_vec())?;
+            let expected: Vec<_> = trie
+                .iter()
+                .filter(|(key, _)| *start <= key.as_slice() && key.as_slice() < *end)
+                .map(|(key, value)| (key, value.clone()))
+                .collect();
+            assert_eq!(proof.entries, expected);
+            assert!(Trie::verify_range_proof(root.clone(), proof)?);
         }
+
+        assert!(matches!(
+            trie.generate_range_proof(&b"b".to_vec(), &b"a".to_vec()),
+            Err(TrieError::InvalidKey)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_proof_with_identical_subtrees() -> Result<()> {
+        // Both leaves hold the same suffix and value, so encode the same
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(vec![1, 9], vec![7; 40])?;
+        trie.insert(vec![2, 9], vec![7; 40])?;
+        let proof = trie.generate_range_proof(&vec![0], &vec![3])?;
+        assert_eq!(proof.entries.len(), 2);
+        assert_eq!(proof.nodes.len(), 2);
+        assert!(Trie::verify_range_proof(trie.root_hash()?, proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_proof_cannot_omit_entries() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..8u8 {
+            trie.insert(vec![1, i], vec![i; 40])?;
+        }
+        let root = trie.root_hash()?;
+        let proof = trie.generate_range_proof(&vec![1, 2], &vec![1, 6])?;
+        assert_eq!(proof.entries.len(), 4);
+
+        // Dropping an entry or changing a value
+        let mut omitted = proof.clone();
+        omitted.entries.remove(1);
+        assert!(!Trie::verify_range_proof(root.clone(), omitted)?);
+        let mut wrong_value = proof.clone();
+        wrong_value.entries[0].1 = vec![9; 40];
+        assert!(!Trie::verify_range_proof(root.clone(), wrong_value)?);
+
+        // Dropping the node of an entry along with the entry
+        let leaf = trie.generate_proof(&vec![1, 3])?.nodes.pop().unwrap();
+        let mut missing = proof.clone();
+        missing.entries.remove(1);
+        missing.nodes.retain(|node| *node != leaf);
+        assert_eq!(missing.nodes.len(), proof.nodes.len() - 1);
+        assert!(Trie::verify_range_proof(root.clone(), missing).is_err());
+
+        // Widening the claimed range past the proven nodes
+        let mut widened = proof.clone();
+        widened.end = vec![1, 7];
+        assert!(Trie::verify_range_proof(root.clone(), widened).is_err());
+
+        // Another root
+        assert!(!Trie::verify_range_proof(vec![0; 32], proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_proofs() -> Result<()> {
+        let keys: &[&[u8]] = &[b"a", b"ab", b"b", b"car", b"cart", b"dog", b"doge"];
+        let trie = build(keys)?;
+        let root = trie.root_hash()?;
+
+        let prefixes: &[&[u8]] = &[b"", b"a", b"c", b"ca", b"car", b"cart", b"cat", b"do", b"e"];
+        for prefix in prefixes {
+            let proof = trie.generate_prefix_proof(&prefix.to_vec())?;
+            let expected: Vec<_> = trie
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .map(|(key, value)| (key, value.clone()))
+                .collect();
+            assert_eq!(proof.entries, expected);
+            assert!(Trie::verify_prefix_proof(root.clone(), proof)?);
+        }
+
+        let empty = Trie::new();
+        let proof = empty.generate_prefix_proof(&b"a".to_vec())?;
+        assert!(proof.entries.is_empty());
+        assert!(Trie::verify_prefix_proof(empty.root_hash()?, proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_proof_cannot_omit_entries() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..8u8 {
+            trie.insert(vec![1, i], vec![i; 40])?;
+            trie.insert(vec![2, i], vec![i + 8; 40])?;
+        }
+        let root = trie.root_hash()?;
+        let proof = trie.generate_prefix_proof(&vec![1])?;
+        assert_eq!(proof.entries.len(), 8);
+
+        let mut omitted = proof.clone();
+        omitted.entries.pop();
+        assert!(!Trie::verify_prefix_proof(root.clone(), omitted)?);
+
+        // Dropping a node below the prefix along with its entry
+        let leaf = trie.generate_proof(&vec![1, 7])?.nodes.pop().unwrap();
+        let mut missing = proof.clone();
+        missing.entries.pop();
+        missing.nodes.retain(|node| *node != leaf);
+        assert!(Trie::verify_prefix_proof(root.clone(), missing).is_err());
+
+        // Claiming a shorter prefix than the nodes cover
+        let mut widened = proof.clone();
+        widened.prefix = vec![];
+        assert!(Trie::verify_prefix_proof(root.clone(), widened).is_err());
+
+        assert!(!Trie::verify_prefix_proof(vec![0; 32], proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_bytes_round_trip() -> Result<()> {
+        let trie = build(&[b"a", b"car", b"cart", b"dog"])?;
+        let proof = trie.generate_proof(&b"cart".to_vec())?;
+
+        let bytes = proof.to_bytes();
+        assert_eq!(bytes[0], PROOF_FORMAT_VERSION);
+        let decoded = MerkleProof::from_bytes(&bytes)?;
+        assert_eq!(decoded, proof);
+        assert!(Trie::verify_proof(trie.root_hash()?, decoded)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_bytes_invalid() -> Result<()> {
+        let trie = build(&[b"a", b"car", b"cart"])?;
+        let bytes = trie.generate_proof(&b"car".to_vec())?.to_bytes();
+
+        // Unknown version, truncated input and trailing bytes
+        let mut version = bytes.clone();
+        version[0] = 2;
+        assert!(MerkleProof::from_bytes(&version).is_err());
+        for len in 0..bytes.len() {
+            assert!(MerkleProof::from_bytes(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(MerkleProof::from_bytes(&trailing).is_err());
+
+        // A node count larger than the input could hold
+        let huge = [&[PROOF_FORMAT_VERSION][..], &[0; 8], &[0xff; 4]].concat();
+        assert!(MerkleProof::from_bytes(&huge).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_multi_proof() -> Result<()> {
+        let trie = build(&[b"a", b"car", b"cart", b"dog"])?;
+        let root = trie.root_hash()?;
+        let proof = trie.generate_multi_proof(&[b"car".to_vec(), b"cow".to_vec()])?;
+        assert_eq!(check_multi_proof(&root, &proof), Ok(()));
+
+        let mut wrong_value = proof.clone();
+        wrong_value.values[1] = Some(vec![1]);
+        assert_eq!(
+            check_multi_proof(&root, &wrong_value),
+            Err(ProofError::ValueMismatch {
+                expected: Some(hash_data(&[1])),
+                computed: None,
+            })
+        );
+
+        let mut unequal = proof.clone();
+        unequal.values.pop();
+        assert_eq!(
+            check_multi_proof(&root, &unequal),
+            Err(ProofError::MalformedProof)
+        );
+
+        let mut duplicate = proof.clone();
+        duplicate.nodes.push(proof.nodes[0].clone());
+        assert_eq!(
+            check_multi_proof(&root, &duplicate),
+            Err(ProofError::DuplicateNode {
+                index: proof.nodes.len()
+            })
+        );
+
+        let mut extra = proof.clone();
+        extra.nodes.push(vec![0x02]);
+        assert_eq!(
+            check_multi_proof(&root, &extra),
+            Err(ProofError::UnusedNode {
+                index: proof.nodes.len()
+            })
+        );
+
+        assert!(matches!(
+            check_multi_proof(&[0; 32], &proof),
+            Err(ProofError::RootMismatch { .. })
+        ));
+
+        // The detail survives the conversion into the trie's errors
+        let err = TrieError::from(check_multi_proof(&root, &unequal).unwrap_err());
+        assert!(matches!(
+            err,
+            TrieError::ProofFailed(ProofError::MalformedProof)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_trait_checks_report_proof_failures() -> Result<()> {
+        let trie = build(&[b"a", b"car", b"cart", b"dog"])?;
+        let root = trie.root_hash()?;
+
+        let proof = trie.generate_proof(&b"car".to_vec())?;
+        Trie::check_proof(root.clone(), proof.clone())?;
+        let mut wrong_value = proof;
+        wrong_value.value = vec![1];
+        assert!(!Trie::verify_proof(root.clone(), wrong_value.clone())?);
+        assert!(matches!(
+            Trie::check_proof(root.clone(), wrong_value),
+            Err(TrieError::ProofFailed(ProofError::ValueMismatch { .. }))
+        ));
+
+        let exclusion = trie.generate_exclusion_proof(&b"cow".to_vec())?;
+        Trie::check_exclusion_proof(root.clone(), exclusion.clone())?;
+        assert!(matches!(
+            Trie::check_exclusion_proof(vec![0; 32], exclusion),
+            Err(TrieError::ProofFailed(ProofError::RootMismatch { .. }))
+        ));
+
+        let multi = trie.generate_multi_proof(&[b"car".to_vec(), b"cow".to_vec()])?;
+        Trie::check_multi_proof(root.clone(), multi.clone())?;
+        let mut unequal = multi;
+        unequal.values.pop();
+        assert!(matches!(
+            Trie::check_multi_proof(root.clone(), unequal),
+            Err(TrieError::ProofFailed(ProofError::MalformedProof))
+        ));
+
+        let range = trie.generate_range_proof(&b"b".to_vec(), &b"d".to_vec())?;
+        Trie::check_range_proof(root.clone(), range.clone())?;
+        let mut omitted = range;
+        omitted.entries.pop();
+        assert!(matches!(
+            Trie::check_range_proof(root.clone(), omitted),
+            Err(TrieError::ProofFailed(_))
+        ));
+
+        let prefix = trie.generate_prefix_proof(&b"ca".to_vec())?;
+        Trie::check_prefix_proof(root.clone(), prefix.clone())?;
+        let mut omitted = prefix;
+        omitted.entries.pop();
+        assert!(matches!(
+            Trie::check_prefix_proof(root, omitted),
+            Err(TrieError::ProofFailed(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_range_and_prefix_proofs() -> Result<()> {
+        let trie = build(&[b"a", b"car", b"cart", b"cat", b"dog"])?;
+        let root = trie.root_hash()?;
+
+        let range = trie.generate_range_proof(&b"b".to_vec(), &b"cat".to_vec())?;
+        assert_eq!(check_range_proof(&root, &range), Ok(()));
+        let mut missing = range.clone();
+        let left_out = missing.entries.remove(0);
+        assert_eq!(
+            check_range_proof(&root, &missing),
+            Err(ProofError::EntryMismatch {
+                index: 0,
+                expected: Some(missing.entries[0].0.clone()),
+                computed: Some(left_out.0),
+            })
+        );
+
+        let prefix = trie.generate_prefix_proof(&b"ca".to_vec())?;
+        assert_eq!(check_prefix_proof(&root, &prefix), Ok(()));
+        let mut wrong_value = prefix.clone();
+        wrong_value.entries[1].1 = vec![1];
+        assert_eq!(
+            check_prefix_proof(&root, &wrong_value),
+            Err(ProofError::ValueMismatch {
+                expected: Some(hash_data(&[1])),
+                computed: Some(hash_data(&prefix.entries[1].1)),
+            })
+        );
+        let mut extra = prefix.clone();
+        extra.entries.push((b"cats".to_vec(), vec![1]));
+        assert_eq!(
+            check_prefix_proof(&root, &extra),
+            Err(ProofError::EntryMismatch {
+                index: prefix.entries.len(),
+                expected: Some(b"cats".to_vec()),
+                computed: None,
+            })
+        );
+
+        // A subtree under the prefix whose node was left out
+        let mut truncated = prefix.clone();
+        let leaf = truncated.nodes.pop().unwrap();
+        assert_eq!(
+            check_prefix_proof(&root, &truncated),
+            Err(ProofError::MissingChild {
+                hash: hash_data(&leaf)
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_all_proofs_bytes_round_trip() -> Result<()> {
+        let trie = build(&[b"a", b"car", b"cart", b"cat", b"dog"])?;
+        let root = trie.root_hash()?;
+
+        let exclusion = trie.generate_exclusion_proof(&b"cab".to_vec())?;
+        let decoded = ExclusionProof::from_bytes(&exclusion.to_bytes())?;
+        assert_eq!(decoded, exclusion);
+        assert!(Trie::verify_exclusion_proof(root.clone(), decoded)?);
+
+        let multi = trie.generate_multi_proof(&[b"car".to_vec(), b"cow".to_vec()])?;
+        let decoded = MultiProof::from_bytes(&multi.to_bytes())?;
+        assert_eq!(decoded, multi);
+        assert!(Trie::verify_multi_proof(root.clone(), decoded)?);
+
+        let range = trie.generate_range_proof(&b"b".to_vec(), &b"cat".to_vec())?;
+        let decoded = RangeProof::from_bytes(&range.to_bytes())?;
+        assert_eq!(decoded, range);
+        assert!(Trie::verify_range_proof(root.clone(), decoded)?);
+
+        let prefix = trie.generate_prefix_proof(&b"ca".to_vec())?;
+        let decoded = PrefixProof::from_bytes(&prefix.to_bytes())?;
+        assert_eq!(decoded, prefix);
+        assert!(Trie::verify_prefix_proof(root, decoded)?);
+
+        let encodings = [
+            exclusion.to_bytes(),
+            multi.to_bytes(),
+            range.to_bytes(),
+            prefix.to_bytes(),
+        ];
+        for bytes in &encodings {
+            assert_eq!(bytes[0], PROOF_FORMAT_VERSION);
+        }
+        // Truncated input and trailing bytes
+        for len in 0..encodings[1].len() {
+            assert!(MultiProof::from_bytes(&encodings[1][..len]).is_err());
+        }
+        for len in 0..encodings[2].len() {
+            assert!(RangeProof::from_bytes(&encodings[2][..len]).is_err());
+        }
+        let mut trailing = encodings[3].clone();
+        trailing.push(0);
+        assert!(PrefixProof::from_bytes(&trailing).is_err());
+        let mut version = encodings[0].clone();
+        version[0] = 2;
+        assert!(ExclusionProof::from_bytes(&version).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_branch_value_proofs() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(b"a".to_vec(), b"short".to_vec())?;
+        trie.insert(b"ab".to_vec(), vec![1; 40])?;
+        trie.insert(b"abc".to_vec(), vec![2; 40])?;
+        trie.insert(b"abd".to_vec(), b"x".to_vec())?;
+        trie.insert(b"b".to_vec(), vec![3; 40])?;
+        let root = trie.root_hash()?;
+
+        for key in [&b"a"[..], b"ab", b"abc", b"abd", b"b"] {
+            let proof = trie.generate_proof(&key.to_vec())?;
+            assert!(Trie::verify_proof(root.clone(), proof)?);
+        }
+
+        // "a" and "ab" hold their values on the branches above longer keys
+        for key in [&b"a"[..], b"ab"] {
+            let proof = trie.generate_proof(&key.to_vec())?;
+            let last = decode_node(proof.nodes.last().unwrap())?;
+            assert!(matches!(last, DecodedNode::Branch { value: Some(v), .. } if v == proof.value));
+        }
+
+        // The value of a longer key does not pass for the branch value
+        let mut proof = trie.generate_proof(&b"ab".to_vec())?;
+        proof.value = vec![2; 40];
+        assert!(!Trie::verify_proof(root.clone(), proof.clone())?);
+        proof.key = b"abc".to_vec();
+        assert!(Trie::verify_proof(root.clone(), proof).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_branch_without_value_excludes_prefix() -> Result<()> {
+        let trie = build(&[b"abc", b"abd"])?;
+        let root = trie.root_hash()?;
+        let proof = trie.generate_exclusion_proof(&b"ab".to_vec())?;
+        assert!(Trie::verify_exclusion_proof(root.clone(), proof)?);
+
+        // Once "ab" has a value it sits on the same branch
+        let mut trie = trie;
+        trie.insert(b"ab".to_vec(), b"value of ab".to_vec())?;
+        let proof = trie.generate_proof(&b"ab".to_vec())?;
+        assert!(Trie::verify_proof(trie.root_hash()?, proof)?);
+        assert!(matches!(
+            trie.generate_exclusion_proof(&b"ab".to_vec()),
+            Err(TrieError::KeyExists)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_branch_value_proof() -> Result<()> {
+        let trie = build(&[b"", b"a", b"b"])?;
+        let proof = trie.generate_proof(&Vec::new())?;
+        assert_eq!(proof.nodes.len(), 1);
+        assert!(Trie::verify_proof(trie.root_hash()?, proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_key_has_no_proof() -> Result<()> {
+        let trie = build(&[b"a", b"b"])?;
+        assert!(matches!(
+            trie.generate_proof(&b"c".to_vec()),
+            Err(TrieError::NodeNotFound)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_ethereum_mode() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::with_mode(HashMode::Ethereum);
+        trie.insert(b"dog".to_vec(), b"puppy".to_vec())?;
+        let key = b"dog".to_vec();
+
+        let unsupported = |result: Result<()>| {
+            matches!(
+                result,
+                Err(TrieError::UnsupportedHashMode(HashMode::Ethereum))
+            )
+        };
+        assert!(unsupported(trie.generate_proof(&key).map(drop)));
+        assert!(unsupported(trie.generate_exclusion_proof(&key).map(drop)));
+        assert!(unsupported(
+            trie.generate_multi_proof(std::slice::from_ref(&key))
+                .map(drop)
+        ));
+        assert!(unsupported(trie.generate_range_proof(&key, &key).map(drop)));
+        assert!(unsupported(trie.generate_prefix_proof(&key).map(drop)));
+        Ok(())
     }
 }
@@ -0,0 +1,334 @@
+//! Recursive Length Prefix (RLP) encoding.
+//!
+//! RLP is the serialization format Ethereum uses for trie nodes. It encodes
+//! two kinds of items, byte strings and lists of items:
+//!
+//! | Item                              | Encoding                                  |
+//! |-----------------------------------|-------------------------------------------|
+//! | Single byte below `0x80`          | The byte itself                           |
+//! | String of 0-55 bytes              | `0x80 + len` then the bytes               |
+//! | String of more than 55 bytes      | `0xb7 + len(len)`, `len`, then the bytes  |
+//! | List with 0-55 payload bytes      | `0xc0 + len` then the payload             |
+//! | List with more than 55 bytes      | `0xf7 + len(len)`, `len`, then the payload |
+//!
+//! Lengths in the long forms are big-endian without leading zeros.
+//!
+//! Decoding is strict: every item must use its shortest encoding and the
+//! input must contain exactly one item, so each item has exactly one valid
+//! encoding. Anything else is rejected with [`TrieError::InvalidRlp`], as
+//! are lists nested deeper than [`MAX_DEPTH`], so that untrusted input
+//! cannot exhaust the stack.
+//!
+//! [`MAX_DEPTH`]: crate::rlp::MAX_DEPTH
+
+use crate::error::{Result, TrieError};
+
+/// Offset of the short string prefix
+const STRING_OFFSET: u8 = 0x80;
+/// Offset of the short list prefix
+const LIST_OFFSET: u8 = 0xc0;
+/// Longest payload that uses the short form
+const SHORT_LIMIT: usize = 55;
+/// Deepest nesting of lists that decoding accepts
+///
+/// Trie nodes nest at most a few levels, through children embedded in
+/// their parent.
+pub const MAX_DEPTH: usize = 64;
+
+/// A decoded RLP item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RlpItem {
+    /// A byte string
+    Bytes(Vec<u8>),
+    /// A list of items
+    List(Vec<RlpItem>),
+}
+
+impl RlpItem {
+    /// Returns the bytes of a string item, or `None` for a list
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            RlpItem::Bytes(bytes) => Some(bytes),
+            RlpItem::List(_) => None,
+        }
+    }
+
+    /// Returns the items of a list, or `None` for a string
+    pub fn as_list(&self) -> Option<&[RlpItem]> {
+        match self {
+            RlpItem::Bytes(_) => None,
+            RlpItem::List(items) => Some(items),
+        }
+    }
+
+    /// Encodes this item
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            RlpItem::Bytes(bytes) => encode_bytes(bytes),
+            RlpItem::List(items) => {
+                let encoded: Vec<Vec<u8>> = items.iter().map(RlpItem::encode).collect();
+                encode_list(&encoded)
+            }
+        }
+    }
+}
+
+/// Writes the prefix for a payload of `len` bytes
+fn encode_header(out: &mut Vec<u8>, offset: u8, len: usize) {
+    if len <= SHORT_LIMIT {
+        out.push(offset + len as u8);
+    } else {
+        let len_bytes = len.to_be_bytes();
+        let skip = len_bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(offset + SHORT_LIMIT as u8 + (len_bytes.len() - skip) as u8);
+        out.extend_from_slice(&len_bytes[skip..]);
+    }
+}
+
+/// Encodes a byte string
+///
+/// # Arguments
+/// * `bytes` - The string to encode
+///
+/// # Returns
+/// The RLP encoding of the string
+pub fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < STRING_OFFSET {
+        return vec![bytes[0]];
+    }
+    let mut out = Vec::with_capacity(bytes.len() + 9);
+    encode_header(&mut out, STRING_OFFSET, bytes.len());
+    out.extend_from_slice(bytes);
+    out
+}
+
+/// Encodes a list from already encoded items
+///
+/// # Arguments
+/// * `items` - RLP encodings of the list items, in order
+///
+/// # Returns
+/// The RLP encoding of the list
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload_len = items.iter().map(Vec::len).sum();
+    let mut out = Vec::with_capacity(payload_len + 9);
+    encode_header(&mut out, LIST_OFFSET, payload_len);
+    for item in items {
+        out.extend_from_slice(item);
+    }
+    out
+}
+
+/// Decodes a single item that must span the whole input
+///
+/// # Arguments
+/// * `data` - The RLP encoding to decode
+///
+/// # Returns
+/// * `Ok(RlpItem)` - The decoded item
+/// * `Err(TrieError::InvalidRlp)` - If the encoding is truncated, not
+///   canonical, nested deeper than [`MAX_DEPTH`], or followed by trailing
+///   bytes
+pub fn decode(data: &[u8]) -> Result<RlpItem> {
+    let (item, rest) = decode_item(data, 0)?;
+    if !rest.is_empty() {
+        return Err(TrieError::InvalidRlp);
+    }
+    Ok(item)
+}
+
+/// Decodes the first item of `data`, returning it with the remaining bytes
+///
+/// `depth` is the number of lists enclosing the item.
+fn decode_item(data: &[u8], depth: usize) -> Result<(RlpItem, &[u8])> {
+    let (is_list, payload, rest) = split_item(data)?;
+    if !is_list {
+        return Ok((RlpItem::Bytes(payload.to_vec()), rest));
+    }
+    if depth >= MAX_DEPTH {
+        return Err(TrieError::InvalidRlp);
+    }
+
+    let mut items = Vec::new();
+    let mut payload = payload;
+    while !payload.is_empty() {
+        let (item, remaining) = decode_item(payload, depth + 1)?;
+        items.push(item);
+        payload = remaining;
+    }
+    Ok((RlpItem::List(items), rest))
+}
+
+/// Splits the first item of `data` into its kind, payload and the bytes after it
+fn split_item(data: &[u8]) -> Result<(bool, &[u8], &[u8])> {
+    let prefix = *data.first().ok_or(TrieError::InvalidRlp)?;
+
+    match prefix {
+        // A single byte is its own encoding
+        0x00..=0x7f => {
+            let (byte, rest) = data.split_at(1);
+            Ok((false, byte, rest))
+        }
+        _ => {
+            let data = &data[1..];
+            let (is_list, offset) = if prefix < LIST_OFFSET {
+                (false, STRING_OFFSET)
+            } else {
+                (true, LIST_OFFSET)
+            };
+            let short = prefix - offset;
+
+            let (len, data) = if short as usize <= SHORT_LIMIT {
+                (short as usize, data)
+            } else {
+                let len_of_len = short as usize - SHORT_LIMIT;
+                if data.len() < len_of_len {
+                    return Err(TrieError::InvalidRlp);
+                }
+                let (len_bytes, data) = data.split_at(len_of_len);
+                // Lengths have no leading zeros and must need the long form
+                if len_bytes[0] == 0 || len_of_len > std::mem::size_of::<usize>() {
+                    return Err(TrieError::InvalidRlp);
+                }
+                let len = len_bytes
+                    .iter()
+                    .fold(0usize, |acc, &b| (acc << 8) | b as usize);
+                if len <= SHORT_LIMIT {
+                    return Err(TrieError::InvalidRlp);
+                }
+                (len, data)
+            };
+
+            if data.len() < len {
+                return Err(TrieError::InvalidRlp);
+            }
+            let (payload, rest) = data.split_at(len);
+
+            // A single byte below 0x80 must be encoded as itself
+            if !is_list && len == 1 && payload[0] < STRING_OFFSET {
+                return Err(TrieError::InvalidRlp);
+            }
+            Ok((is_list, payload, rest))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(data: &[u8]) -> RlpItem {
+        RlpItem::Bytes(data.to_vec())
+    }
+
+    #[test]
+    fn test_encode_bytes() {
+        assert_eq!(encode_bytes(&[]), vec![0x80]);
+        assert_eq!(encode_bytes(&[0x0f]), vec![0x0f]);
+        assert_eq!(encode_bytes(&[0x80]), vec![0x81, 0x80]);
+        assert_eq!(encode_bytes(b"dog"), vec![0x83, b'd', b'o', b'g']);
+
+        let long = [b'a'; 56];
+        let encoded = encode_bytes(&long);
+        assert_eq!(&encoded[..2], &[0xb8, 56]);
+        assert_eq!(&encoded[2..], &long[..]);
+    }
+
+    #[test]
+    fn test_encode_list() {
+        assert_eq!(encode_list(&[]), vec![0xc0]);
+
+        let cat_dog = RlpItem::List(vec![bytes(b"cat"), bytes(b"dog")]);
+        assert_eq!(
+            cat_dog.encode(),
+            vec![0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']
+        );
+
+        // The set theoretical representation of three
+        let three = RlpItem::List(vec![
+            RlpItem::List(vec![]),
+            RlpItem::List(vec![RlpItem::List(vec![])]),
+            RlpItem::List(vec![
+                RlpItem::List(vec![]),
+                RlpItem::List(vec![RlpItem::List(vec![])]),
+            ]),
+        ]);
+        assert_eq!(
+            three.encode(),
+            vec![0xc7, 0xc0, 0xc1, 0xc0, 0xc3, 0xc0, 0xc1, 0xc0]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let items = vec![
+            bytes(&[]),
+            bytes(&[0x00]),
+            bytes(&[0x7f]),
+            bytes(&[0x80]),
+            bytes(&[0xab; 55]),
+            bytes(&[0xab; 56]),
+            bytes(&[0xab; 1024]),
+            RlpItem::List(vec![]),
+            RlpItem::List(vec![bytes(&[0xcd; 60]), RlpItem::List(vec![bytes(b"x")])]),
+        ];
+        for item in items {
+            assert_eq!(decode(&item.encode()).unwrap(), item);
+        }
+    }
+
+    #[test]
+    fn test_rejects_non_canonical() {
+        // Single byte below 0x80 wrapped in a string prefix
+        assert!(decode(&[0x81, 0x05]).is_err());
+        // Long form used for a short string
+        assert!(decode(&[0xb8, 0x01, 0x80]).is_err());
+        // Length with a leading zero
+        let mut padded = vec![0xb9, 0x00, 56];
+        padded.extend_from_slice(&[0; 56]);
+        assert!(decode(&padded).is_err());
+        // Long form used for a short list
+        assert!(decode(&[0xf8, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_malformed() {
+        assert!(decode(&[]).is_err());
+        // Truncated payloads
+        assert!(decode(&[0x83, b'd', b'o']).is_err());
+        assert!(decode(&[0xb8]).is_err());
+        assert!(decode(&[0xc2, 0x83]).is_err());
+        // Trailing bytes
+        assert!(decode(&[0x01, 0x02]).is_err());
+        // List item overrunning the list payload
+        assert!(decode(&[0xc1, 0x82, 0x01, 0x02]).is_err());
+    }
+
+    /// Encodes `depth` lists nested inside each other, the innermost empty
+    fn nested(depth: usize) -> Vec<u8> {
+        let mut headers = Vec::new();
+        let mut len = 1;
+        for _ in 1..depth {
+            let mut header = Vec::new();
+            encode_header(&mut header, LIST_OFFSET, len);
+            len += header.len();
+            headers.push(header);
+        }
+        let mut out: Vec<u8> = headers.into_iter().rev().flatten().collect();
+        out.push(LIST_OFFSET);
+        out
+    }
+
+    #[test]
+    fn test_rejects_deep_nesting() {
+        assert!(decode(&nested(MAX_DEPTH)).is_ok());
+        assert!(matches!(
+            decode(&nested(MAX_DEPTH + 1)),
+            Err(TrieError::InvalidRlp)
+        ));
+        // Far too deep to decode recursively
+        let deep = nested(200_000);
+        assert!(deep.len() > 700_000);
+        assert!(matches!(decode(&deep), Err(TrieError::InvalidRlp)));
+    }
+}
@@ -0,0 +1,259 @@
+//! Secure trie mode with hashed keys.
+//!
+//! A [`SecureTrie`] hashes every key before it reaches the underlying
+//! Patricia Merkle Trie. All paths therefore have the same length and are
+//! uniformly distributed, which keeps the trie balanced and prevents callers
+//! from forcing deep paths with adversarial keys. This mirrors Ethereum's
+//! `SecureTrie`, and a secure trie in [`HashMode::Ethereum`] hashes its keys
+//! with Keccak-256 as Ethereum does, where the native mode uses SHA-256.
+//!
+//! Since the trie only sees hashed keys, an optional preimage table can be
+//! enabled to map hashed keys back to the original keys for iteration and
+//! debugging. The preimages are not part of the root hash.
+//!
+//! [`HashMode::Ethereum`]: crate::HashMode::Ethereum
+
+use crate::{
+    error::Result,
+    eth::keccak256,
+    hash::hash_data,
+    trie::{HashMode, PatriciaMerkleTrie},
+};
+use std::collections::HashMap;
+
+/// A Patricia Merkle Trie whose keys are hashed before insertion
+///
+/// # Type Parameters
+/// - `K`: Original key type that can be converted to a byte slice
+/// - `V`: Value type that can be converted to byte slices
+///
+/// # Examples
+/// ```
+/// # use patricia_merkle_trie::{Result, SecureTrie};
+/// # fn main() -> Result<()> {
+/// let mut trie = SecureTrie::with_preimages();
+/// trie.insert(b"alice".to_vec(), b"100".to_vec())?;
+/// assert_eq!(trie.get(&b"alice".to_vec())?.unwrap(), b"100");
+///
+/// let hashed = trie.hash_key(b"alice");
+/// assert_eq!(trie.preimage(&hashed).unwrap(), b"alice");
+/// # Ok(())
+/// # }
+/// ```
+pub struct SecureTrie<K, V> {
+    trie: PatriciaMerkleTrie<Vec<u8>, V>,
+    preimages: Option<HashMap<Vec<u8>, K>>,
+}
+
+impl<K, V> SecureTrie<K, V>
+where
+    K: AsRef<[u8]> + Clone,
+    V: Clone + AsRef<[u8]> + std::fmt::Debug,
+{
+    /// Creates a new empty secure trie without a preimage store
+    pub fn new() -> Self {
+        SecureTrie {
+            trie: PatriciaMerkleTrie::new(),
+            preimages: None,
+        }
+    }
+
+    /// Creates a new empty secure trie that records key preimages
+    pub fn with_preimages() -> Self {
+        SecureTrie {
+            trie: PatriciaMerkleTrie::new(),
+            preimages: Some(HashMap::new()),
+        }
+    }
+
+    /// Creates a new empty secure trie without a preimage store whose root
+    /// hash and key hash are computed according to `mode`
+    ///
+    /// # Examples
+    /// ```
+    /// # use patricia_merkle_trie::{eth::keccak256, HashMode, Result, SecureTrie};
+    /// # fn main() -> Result<()> {
+    /// let mut trie = SecureTrie::with_mode(HashMode::Ethereum);
+    /// trie.insert(b"alice".to_vec(), b"100".to_vec())?;
+    /// assert_eq!(trie.hash_key(b"alice"), keccak256(b"alice"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_mode(mode: HashMode) -> Self {
+        SecureTrie {
+            trie: PatriciaMerkleTrie::with_mode(mode),
+            preimages: None,
+        }
+    }
+
+    /// Hashes an original key into the key stored in the trie
+    ///
+    /// Keys are hashed with Keccak-256 in [`HashMode::Ethereum`], as in
+    /// Ethereum's state and storage tries, and with SHA-256 otherwise.
+    ///
+    /// # Arguments
+    /// * `key` - Original key bytes
+    ///
+    /// # Returns
+    /// The 32-byte hashed key
+    pub fn hash_key(&self, key: &[u8]) -> Vec<u8> {
+        match self.trie.mode() {
+            HashMode::Native => hash_data(key),
+            HashMode::Ethereum => keccak256(key),
+        }
+    }
+
+    /// Inserts a key-value pair into the trie
+    ///
+    /// # Arguments
+    /// * `key` - The original key, hashed before insertion
+    /// * `value` - The value to associate with the key
+    ///
+    /// # Returns
+    /// * `Ok(())` on successful insertion
+    /// * `Err(TrieError)` if the insertion fails
+    pub fn insert(&mut self, key: K, value: V) -> Result<()> {
+        let hashed_key = self.hash_key(key.as_ref());
+        self.trie.insert(hashed_key.clone(), value)?;
+        if let Some(preimages) = &mut self.preimages {
+            preimages.insert(hashed_key, key);
+        }
+        Ok(())
+    }
+
+    /// Retrieves a value by its original key
+    ///
+    /// # Returns
+    /// * `Ok(Some(&V))` if the key exists
+    /// * `Ok(None)` if the key doesn't exist
+    pub fn get(&self, key: &K) -> Result<Option<&V>> {
+        self.trie.get(&self.hash_key(key.as_ref()))
+    }
+
+    /// Deletes a key-value pair by its original key
+    ///
+    /// The preimage of the key is removed along with the value.
+    ///
+    /// # Returns
+    /// * `Ok(Some(V))` if the key was found and deleted
+    /// * `Ok(None)` if the key didn't exist
+    pub fn delete(&mut self, key: &K) -> Result<Option<V>> {
+        let hashed_key = self.hash_key(key.as_ref());
+        let value = self.trie.delete(&hashed_key)?;
+        if let Some(preimages) = &mut self.preimages {
+            preimages.remove(&hashed_key);
+        }
+        Ok(value)
+    }
+
+    /// Computes the cryptographic hash of the entire trie
+    pub fn root_hash(&self) -> Result<Vec<u8>> {
+        self.trie.root_hash()
+    }
+
+    /// Looks up the original key for a hashed key
+    ///
+    /// # Returns
+    /// The original key if preimages are recorded and the key is known
+    pub fn preimage(&self, hashed_key: &[u8]) -> Option<&K> {
+        self.preimages.as_ref()?.get(hashed_key)
+    }
+
+    /// Returns an iterator over all entries in hashed key order
+    ///
+    /// Each item holds the hashed key, the original key if it can be
+    /// recovered from the preimage store, and the value.
+    pub fn iter(&self) -> impl Iterator<Item = (Vec<u8>, Option<&K>, &V)> + '_ {
+        self.trie.iter().map(move |(hashed_key, value)| {
+            let key = self.preimage(&hashed_key);
+            (hashed_key, key, value)
+        })
+    }
+
+    /// Returns the underlying trie keyed by hashed keys
+    ///
+    /// This can be used to generate proofs for `hash_key(key)`.
+    pub fn trie(&self) -> &PatriciaMerkleTrie<Vec<u8>, V> {
+        &self.trie
+    }
+}
+
+impl<K, V> Default for SecureTrie<K, V>
+where
+    K: AsRef<[u8]> + Clone,
+    V: Clone + AsRef<[u8]> + std::fmt::Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_are_hashed() -> Result<()> {
+        let mut trie = SecureTrie::new();
+        trie.insert(b"key".to_vec(), b"value".to_vec())?;
+
+        let hashed = trie.hash_key(b"key");
+        assert_eq!(hashed, hash_data(b"key"));
+        assert_eq!(trie.trie().get(&hashed)?.unwrap(), b"value");
+        assert!(trie.trie().get(&b"key".to_vec())?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_keys_are_accepted() -> Result<()> {
+        let mut trie = SecureTrie::new();
+        let key = vec![7u8; 100];
+        trie.insert(key.clone(), b"value".to_vec())?;
+        assert_eq!(trie.get(&key)?.unwrap(), b"value");
+        Ok(())
+    }
+
+    #[test]
+    fn test_preimages() -> Result<()> {
+        let mut trie = SecureTrie::with_preimages();
+        trie.insert(b"a".to_vec(), b"1".to_vec())?;
+        trie.insert(b"b".to_vec(), b"2".to_vec())?;
+
+        let mut keys: Vec<_> = trie
+            .iter()
+            .map(|(_, key, _)| key.unwrap().clone())
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec![b"a".to_vec(), b"b".to_vec()]);
+
+        trie.delete(&b"a".to_vec())?;
+        let hashed = trie.hash_key(b"a");
+        assert!(trie.preimage(&hashed).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ethereum_mode_hashes_with_keccak() -> Result<()> {
+        let mut secure = SecureTrie::with_mode(HashMode::Ethereum);
+        let mut plain = PatriciaMerkleTrie::with_mode(HashMode::Ethereum);
+        for (key, value) in [(&b"doe"[..], &b"reindeer"[..]), (b"dog", b"puppy")] {
+            secure.insert(key.to_vec(), value.to_vec())?;
+            plain.insert(keccak256(key), value.to_vec())?;
+        }
+        assert_eq!(secure.hash_key(b"dog"), keccak256(b"dog"));
+        assert_eq!(secure.root_hash()?, plain.root_hash()?);
+        assert_eq!(secure.get(&b"dog".to_vec())?.unwrap(), b"puppy");
+        Ok(())
+    }
+
+    #[test]
+    fn test_without_preimages() -> Result<()> {
+        let mut trie = SecureTrie::new();
+        trie.insert(b"a".to_vec(), b"1".to_vec())?;
+
+        let entries: Vec<_> = trie.iter().collect();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].1.is_none());
+        Ok(())
+    }
+}
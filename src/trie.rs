@@ -6,11 +6,35 @@
 //! - Efficient storage and retrieval of key-value pairs
 use crate::{
     error::{Result, TrieError},
-    hash::{hash_branch, hash_empty, hash_leaf},
-    node::Node,
-    utils::{common_prefix, to_nibbles, verify_key},
+    eth::EthTrie,
+    hash::{
+        encode_branch, encode_empty, encode_extension, encode_leaf, hash_data, node_reference,
+        HASH_LEN,
+    },
+    mutation::{self, NodeBuilder, NodeView, View},
+    node::{Children, Node},
+    utils::{to_nibbles, verify_key},
 };
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::OnceLock};
+
+/// Selects how the root hash of a trie is computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashMode {
+    /// SHA-256 over the tagged node layout of the `hash` module
+    #[default]
+    Native,
+    /// Keccak-256 over RLP encoded nodes, identical to Ethereum's Merkle
+    /// Patricia Trie
+    ///
+    /// The Ethereum encoding is rebuilt from all key-value pairs the first
+    /// time the root hash is read after an insertion or deletion, which
+    /// takes time linear in the size of the trie. Reading the root once
+    /// after a batch of changes costs one rebuild, while reading it after
+    /// every change makes a sequence of n changes quadratic. The nodes are
+    /// not hashed with SHA-256 as they change, as the native root, proofs
+    /// and witnesses are not available in this mode.
+    Ethereum,
+}
 
 /// A Patricia Merkle Trie implementation that stores key-value pairs
 /// with cryptographic verification capabilities.
@@ -32,6 +56,10 @@ use std::collections::HashMap;
 pub struct PatriciaMerkleTrie<K, V> {
     root: Node<K, Option<V>>,
     node_store: HashMap<Vec<u8>, Node<K, Option<V>>>,
+    mode: HashMode,
+    /// Encoding in [`HashMode::Ethereum`], built on first use after each
+    /// change
+    eth_trie: OnceLock<EthTrie>,
 }
 
 impl<K, V> PatriciaMerkleTrie<K, V>
@@ -41,12 +69,37 @@ where
 {
     /// Creates a new empty Patricia Merkle Trie
     pub fn new() -> Self {
+        Self::with_mode(HashMode::Native)
+    }
+
+    /// Creates a new empty Patricia Merkle Trie whose root hash is computed
+    /// according to `mode`
+    ///
+    /// # Examples
+    /// ```
+    /// # use patricia_merkle_trie::{HashMode, PatriciaMerkleTrie, Result};
+    /// # fn main() -> Result<()> {
+    /// let mut trie = PatriciaMerkleTrie::with_mode(HashMode::Ethereum);
+    /// trie.insert(b"foo".to_vec(), b"bar".to_vec())?;
+    /// trie.insert(b"food".to_vec(), b"bass".to_vec())?;
+    /// assert_eq!(trie.root_hash()?[..4], [0x17, 0xbe, 0xaa, 0x16]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_mode(mode: HashMode) -> Self {
         PatriciaMerkleTrie {
             root: Node::Empty,
             node_store: HashMap::new(),
+            mode,
+            eth_trie: OnceLock::new(),
         }
     }
 
+    /// Returns how the root hash of this trie is computed
+    pub fn mode(&self) -> HashMode {
+        self.mode
+    }
+
     /// Returns a reference to the root node
     pub fn root(&self) -> &Node<K, Option<V>> {
         &self.root
@@ -62,198 +115,62 @@ where
     /// * `Ok(())` on successful insertion
     /// * `Err(TrieError)` if the key is invalid
     pub fn insert(&mut self, key: K, value: V) -> Result<()> {
-        println!("Inserting key: {:?}, value: {:?}", key, value);
         verify_key(key.as_ref())?;
+        self.insert_unchecked(key, value)
+    }
+
+    /// Inserts a key-value pair without validating the key length
+    ///
+    /// Used by wrappers whose key length is already guaranteed by their types.
+    pub(crate) fn insert_unchecked(&mut self, key: K, value: V) -> Result<()> {
         let key_nibbles = to_nibbles(key.as_ref())?;
-        println!("Key nibbles: {:?}", key_nibbles);
-        self.root = self.insert_at(self.root.clone(), key.clone(), key_nibbles, Some(value))?;
+        let new_root = self.insert_at(self.root.clone(), key_nibbles, value)?;
+        self.root = self.store_root(new_root)?;
         Ok(())
     }
 
-    /// Internal method to recursively insert a key-value pair
+    /// Internal method to insert a key-value pair below a node
     ///
     /// # Arguments
     /// * `node` - Current node being processed
-    /// * `key` - Full key being inserted
-    /// * `nibbles` - Remaining nibbles of the key to process
+    /// * `nibbles` - Remaining nibbles of the key to process, relative to `node`
     /// * `value` - Value to insert
-    #[allow(clippy::only_used_in_recursion)]
     fn insert_at(
         &mut self,
         node: Node<K, Option<V>>,
-        key: K,
         nibbles: Vec<u8>,
-        value: Option<V>,
+        value: V,
     ) -> Result<Node<K, Option<V>>> {
-        println!("Inserting at node: {:?}, nibbles: {:?}", node, nibbles);
-        let new_node = match node {
-            Node::Empty => {
-                let leaf = Node::Leaf {
-                    key: key.clone(),
-                    value: value.clone(),
-                };
-                // Store the leaf node
-                let hash = self.hash_node(&leaf)?;
-                self.node_store.insert(hash, leaf.clone());
-                leaf
-            }
-            Node::Leaf {
-                key: existing_key,
-                value: existing_value,
-            } => {
-                let existing_nibbles = to_nibbles(existing_key.as_ref())?;
-                let prefix_len = common_prefix(&existing_nibbles, &nibbles);
-
-                if prefix_len == existing_nibbles.len() && prefix_len == nibbles.len() {
-                    // Same key, just update value
-                    let leaf = Node::Leaf {
-                        key: key.clone(),
-                        value: value.clone(),
-                    };
-                    let hash = self.hash_node(&leaf)?;
-                    self.node_store.insert(hash, leaf.clone());
-                    leaf
-                } else {
-                    // Create a new branch
-                    let mut children = HashMap::new();
-                    let prefix = if prefix_len > 0 {
-                        existing_key.as_ref()[..prefix_len].to_vec()
-                    } else {
-                        vec![]
-                    };
-
-                    // Add existing leaf if it has remaining nibbles
-                    if prefix_len < existing_nibbles.len() {
-                        let existing_branch_key = existing_nibbles[prefix_len];
-                        let existing_leaf = Node::Leaf {
-                            key: existing_key.clone(),
-                            value: existing_value.clone(),
-                        };
-                        let existing_hash = self.hash_node(&existing_leaf)?;
-                        self.node_store
-                            .insert(existing_hash.clone(), existing_leaf.clone());
-                        children.insert(existing_branch_key, Box::new(existing_leaf));
-                    }
-
-                    // Add new leaf if it has remaining nibbles
-                    if prefix_len < nibbles.len() {
-                        let new_branch_key = nibbles[prefix_len];
-                        let new_leaf = Node::Leaf {
-                            key: key.clone(),
-                            value: value.clone(),
-                        };
-                        let new_hash = self.hash_node(&new_leaf)?;
-                        self.node_store.insert(new_hash.clone(), new_leaf.clone());
-                        children.insert(new_branch_key, Box::new(new_leaf));
-                    }
+        mutation::insert(self, node, &nibbles, 0, value)
+    }
 
-                    // Create and store the branch node
-                    let branch = Node::Branch {
-                        prefix: prefix.into(),
-                        children,
-                        value: if prefix_len == nibbles.len() {
-                            value
-                        } else {
-                            None
-                        },
-                    };
-                    let branch_hash = self.hash_node(&branch)?;
-                    self.node_store.insert(branch_hash, branch.clone());
-                    branch
-                }
-            }
-            Node::Branch {
-                prefix,
-                mut children,
-                value,
-            } => {
-                let prefix_nibbles = to_nibbles(prefix.as_ref())?;
-                let prefix_len = common_prefix(&prefix_nibbles, &nibbles);
-
-                if prefix_len < prefix_nibbles.len() {
-                    // Split the branch
-                    let new_prefix = prefix.as_ref()[..prefix_len].to_vec();
-                    let mut new_children = HashMap::new();
-
-                    // Create sub-branch for existing children
-                    let remaining_prefix = prefix.as_ref()[prefix_len..].to_vec();
-                    let sub_branch = Node::Branch {
-                        prefix: remaining_prefix.into(),
-                        children,
-                        value: value.clone(),
-                    };
-                    let sub_hash = self.hash_node(&sub_branch)?;
-                    self.node_store.insert(sub_hash.clone(), sub_branch.clone());
-                    new_children.insert(prefix_nibbles[prefix_len], Box::new(sub_branch));
-
-                    // Add new leaf
-                    let new_leaf = Node::Leaf {
-                        key: key.clone(),
-                        value: value.clone(),
-                    };
-                    let new_hash = self.hash_node(&new_leaf)?;
-                    self.node_store.insert(new_hash.clone(), new_leaf.clone());
-                    new_children.insert(nibbles[prefix_len], Box::new(new_leaf));
-
-                    // Create and store new branch
-                    let new_branch = Node::Branch {
-                        prefix: new_prefix.into(),
-                        children: new_children,
-                        value: None,
-                    };
-                    let branch_hash = self.hash_node(&new_branch)?;
-                    self.node_store.insert(branch_hash, new_branch.clone());
-                    new_branch
-                } else {
-                    let remaining_nibbles = nibbles[prefix_len..].to_vec();
-                    if remaining_nibbles.is_empty() {
-                        // Update branch value
-                        let branch = Node::Branch {
-                            prefix,
-                            children,
-                            value: value.clone(),
-                        };
-                        let hash = self.hash_node(&branch)?;
-                        self.node_store.insert(hash, branch.clone());
-                        branch
-                    } else {
-                        let child_nibble = remaining_nibbles[0];
-                        let child = children
-                            .remove(&child_nibble)
-                            .unwrap_or(Box::new(Node::Empty));
-
-                        // Recursively insert into child
-                        let new_child = self.insert_at(
-                            *child,
-                            key.clone(),
-                            remaining_nibbles.clone(), // Pass all remaining nibbles
-                            value.clone(),
-                        )?;
-
-                        // Store the new child
-                        let child_hash = self.hash_node(&new_child)?;
-                        self.node_store.insert(child_hash, new_child.clone());
-
-                        // Update branch with new child
-                        children.insert(child_nibble, Box::new(new_child));
-                        let branch = Node::Branch {
-                            prefix,
-                            children,
-                            value: if remaining_nibbles.len() == 1 {
-                                value
-                            } else {
-                                None
-                            },
-                        };
-                        let hash = self.hash_node(&branch)?;
-                        self.node_store.insert(hash, branch.clone());
-                        branch
-                    }
-                }
-            }
-        };
+    /// Records a node in the node store under its hash
+    ///
+    /// Nodes that are embedded in their parent are not stored. In
+    /// [`HashMode::Ethereum`] nothing is stored, as the native hashes are
+    /// never used.
+    fn store_node(&mut self, node: Node<K, Option<V>>) -> Result<Node<K, Option<V>>> {
+        if self.mode != HashMode::Native {
+            return Ok(node);
+        }
+        let encoded = self.encode_node(&node)?;
+        if encoded.len() >= HASH_LEN {
+            self.node_store.insert(hash_data(&encoded), node.clone());
+        }
+        Ok(node)
+    }
 
-        Ok(new_node)
+    /// Records the root node in the node store under the root hash
+    ///
+    /// The root has no parent to be embedded in, so it is always stored.
+    /// Storing a new root also drops the cached Ethereum encoding.
+    fn store_root(&mut self, root: Node<K, Option<V>>) -> Result<Node<K, Option<V>>> {
+        self.eth_trie.take();
+        if self.mode == HashMode::Native && !root.is_empty() {
+            let root_hash = self.hash_node(&root)?;
+            self.node_store.insert(root_hash, root.clone());
+        }
+        Ok(root)
     }
 
     /// Retrieves a value by key from the trie
@@ -267,9 +184,13 @@ where
     /// * `Err(TrieError)` if the key is invalid
     pub fn get<'a>(&'a self, key: &K) -> Result<Option<&'a V>> {
         verify_key(key.as_ref())?;
+        self.get_unchecked(key)
+    }
+
+    /// Retrieves a value without validating the key length
+    pub(crate) fn get_unchecked<'a>(&'a self, key: &K) -> Result<Option<&'a V>> {
         let key_nibbles = to_nibbles(key.as_ref())?;
-        println!("Getting key: {:?}, nibbles: {:?}", key, key_nibbles); // Debug print
-        self.get_at(&self.root, key_nibbles, key.as_ref())
+        self.get_at(&self.root, key_nibbles)
     }
 
     /// Internal method to recursively search for a key
@@ -278,79 +199,35 @@ where
         &'a self,
         node: &'a Node<K, Option<V>>,
         nibbles: Vec<u8>,
-        original_key: &[u8],
     ) -> Result<Option<&'a V>> {
-        println!("Getting at node: {:?}, nibbles: {:?}", node, nibbles); // Debug print
-        if node.is_empty() {
-            return Ok(None);
-        }
-
-        if node.is_leaf() {
-            if let Node::Leaf { key, value } = node {
-                let existing_nibbles = to_nibbles(key.as_ref())?;
-                println!(
-                    "Leaf node found. Key: {:?}, existing nibbles: {:?}",
-                    key, existing_nibbles
-                ); // Debug print
-                if original_key == key.as_ref() {
-                    return Ok(value.as_ref());
+        match node {
+            Node::Empty => Ok(None),
+            Node::Leaf { path, value } => {
+                if nibbles == to_nibbles(path.as_ref())? {
+                    Ok(value.as_ref())
                 } else {
-                    return Ok(None);
+                    Ok(None)
                 }
             }
-        }
-
-        if let Node::Branch {
-            ref prefix,
-            children,
-            value,
-        } = node
-        {
-            println!("\nHandling branch node");
-            println!("Branch prefix bytes: {:?}", prefix.as_ref());
-            let prefix_nibbles = match to_nibbles(prefix.as_ref()) {
-                Ok(n) => {
-                    println!("Successfully converted prefix to nibbles: {:?}", n);
-                    n
-                }
-                Err(e) => {
-                    println!("Error converting prefix to nibbles: {:?}", e);
-                    return Err(e);
+            Node::Extension { prefix, child } => {
+                let prefix_nibbles = to_nibbles(prefix.as_ref())?;
+                if !nibbles.starts_with(&prefix_nibbles) {
+                    return Ok(None);
                 }
-            };
-
-            println!("Input nibbles: {:?}", nibbles);
-            let prefix_len = common_prefix(&nibbles, &prefix_nibbles);
-
-            println!(
-                "Branch node found. Prefix: {:?}, prefix nibbles: {:?}, prefix_len: {}",
-                prefix, prefix_nibbles, prefix_len
-            ); // Debug print
-
-            // If we've matched the entire prefix
-            if prefix_len == prefix_nibbles.len() {
-                // If we've consumed all nibbles and this branch has a value, return it
-                if nibbles.len() == prefix_len {
+                self.get_at(child, nibbles[prefix_nibbles.len()..].to_vec())
+            }
+            Node::Branch { children, value } => {
+                // If we've consumed all nibbles, the value is stored on this branch
+                if nibbles.is_empty() {
                     return Ok(value.as_ref());
                 }
-
-                // Only try to get child_key if we have more nibbles
-                if nibbles.len() > prefix_len {
-                    let child_nibble = nibbles[prefix_len];
-                    if let Some(child) = children.get(&child_nibble) {
-                        // Recursively search in the child node with the remaining nibbles
-                        return self.get_at(
-                            child,
-                            nibbles[prefix_len + 1..].to_vec(),
-                            original_key,
-                        );
-                    }
+                match children.get(nibbles[0]) {
+                    // Recursively search in the child node with the remaining nibbles
+                    Some(child) => self.get_at(child, nibbles[1..].to_vec()),
+                    None => Ok(None),
                 }
             }
-            return Ok(None);
         }
-
-        Ok(None)
     }
 
     /// Deletes a key-value pair from the trie
@@ -363,197 +240,63 @@ where
     /// * `Ok(None)` if the key didn't exist
     /// * `Err(TrieError)` if the key is invalid
     pub fn delete(&mut self, key: &K) -> Result<Option<V>> {
-        println!("Deleting key: {:?}", key); // Debug print
         verify_key(key.as_ref())?;
+        self.delete_unchecked(key)
+    }
+
+    /// Deletes a key-value pair without validating the key length
+    pub(crate) fn delete_unchecked(&mut self, key: &K) -> Result<Option<V>> {
         let key_nibbles = to_nibbles(key.as_ref())?;
-        println!("Key nibbles: {:?}", key_nibbles); // Debug print
-        let (new_root, value) = self.delete_at(self.root.clone(), key_nibbles, key.as_ref())?;
-        println!(
-            "After delete_at, new_root: {:?}, value: {:?}",
-            new_root, value
-        );
-        match new_root {
-            Node::Empty => {
-                println!("Setting root to empty");
-                self.root = Node::Empty;
-            }
-            _ => {
-                let root_hash = self.hash_node(&new_root)?;
-                self.node_store.insert(root_hash, new_root.clone());
-                self.root = new_root;
-            }
-        }
-        println!("Final root after deletion: {:?}", self.root);
+        let (new_root, value) = mutation::delete(self, self.root.clone(), &key_nibbles, 0)?;
+        self.root = self.store_root(new_root)?;
         Ok(value)
     }
 
-    /// Internal method to recursively delete a key-value pair
-    #[allow(clippy::only_used_in_recursion)]
-    fn delete_at(
-        &mut self,
-        node: Node<K, Option<V>>,
-        nibbles: Vec<u8>,
-        original_key: &[u8],
-    ) -> Result<(Node<K, Option<V>>, Option<V>)> {
-        println!("Deleting at node: {:?}, nibbles: {:?}", node, nibbles); // Debug print
+    /// Returns an iterator over all key-value pairs in the trie
+    ///
+    /// Entries are yielded in lexicographic key order.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &V)> + '_ {
+        let mut entries = Vec::new();
+        Self::collect_entries(&self.root, Vec::new(), &mut entries);
+        entries.into_iter()
+    }
+
+    /// Internal method to recursively collect the entries below a node
+    ///
+    /// # Arguments
+    /// * `node` - Current node being visited
+    /// * `path` - Nibbles leading to `node`
+    /// * `entries` - Output list of key-value pairs
+    fn collect_entries<'a>(
+        node: &'a Node<K, Option<V>>,
+        path: Vec<u8>,
+        entries: &mut Vec<(K, &'a V)>,
+    ) {
         match node {
-            Node::Empty => Ok((Node::Empty, None)),
-            Node::Leaf { key, value } => {
-                let existing_nibbles = to_nibbles(key.as_ref())?;
-                let original_nibbles = to_nibbles(original_key)?;
-                println!(
-                    "Comparing nibbles: existing={:?}, original={:?}",
-                    existing_nibbles, original_nibbles
-                );
-                if existing_nibbles != original_nibbles {
-                    println!("Nibbles don't match, keeping leaf");
-                    return Ok((Node::Leaf { key, value }, None));
-                }
-                println!(
-                    "Found leaf node to delete with key: {:?}, value: {:?}",
-                    key, value
-                );
-                println!("Returning Empty node and value: {:?}", value);
-                Ok((Node::Empty, value))
-            }
-            Node::Branch {
-                prefix,
-                mut children,
+            Node::Empty => {}
+            Node::Leaf {
+                path: suffix,
                 value,
             } => {
-                let prefix_nibbles = to_nibbles(prefix.as_ref())?;
-                let common_len = common_prefix(&prefix_nibbles, &nibbles);
-
-                if common_len < prefix_nibbles.len() {
-                    // Key not in this branch
-                    return Ok((
-                        Node::Branch {
-                            prefix,
-                            children,
-                            value,
-                        },
-                        None,
-                    ));
+                if let Some(value) = value {
+                    let mut key = path;
+                    key.extend_from_slice(suffix.as_ref());
+                    entries.push((key.into(), value));
                 }
-
-                let remaining_nibbles = nibbles[common_len..].to_vec();
-                if remaining_nibbles.is_empty() {
-                    // This is the target branch, remove its value
-                    if children.is_empty() {
-                        Ok((Node::Empty, value))
-                    } else {
-                        let branch = Node::Branch {
-                            prefix,
-                            children,
-                            value: None,
-                        };
-                        let hash = self.hash_node(&branch)?;
-                        self.node_store.insert(hash, branch.clone());
-                        Ok((branch, value))
-                    }
-                } else {
-                    let child_nibble = remaining_nibbles[0];
-                    println!("Looking for child with nibble: {:?}", child_nibble);
-                    println!("Children before removal: {:?}", children);
-                    if let Some(child) = children.remove(&child_nibble) {
-                        println!("Found child to delete: {:?}", child);
-                        let (new_child, deleted_value) =
-                            self.delete_at(*child, remaining_nibbles[1..].to_vec(), original_key)?;
-                        println!(
-                            "After recursive delete, new_child: {:?}, deleted_value: {:?}",
-                            new_child, deleted_value
-                        );
-
-                        match new_child {
-                            Node::Empty => {
-                                println!("Child was deleted, children map now: {:?}", children);
-                                // Child was deleted, don't put it back
-                                if children.is_empty() && value.is_none() {
-                                    println!("No more children and no value, converting to empty");
-                                    // No more children and no value, convert to empty node
-                                    Ok((Node::Empty, deleted_value))
-                                } else if children.len() == 1 && value.is_none() {
-                                    println!("Only one child left, collapsing branch");
-                                    // Only one child left and no value, collapse the branch
-                                    let (remaining_nibble, remaining_child) =
-                                        children.into_iter().next().unwrap();
-                                    let child = *remaining_child;
-                                    match child {
-                                        Node::Leaf { key, value } => {
-                                            // Create a new leaf with the combined prefix
-                                            let mut new_key = prefix.as_ref().to_vec();
-                                            new_key.push(remaining_nibble);
-                                            let leaf = Node::Leaf {
-                                                key: new_key.into(),
-                                                value,
-                                            };
-                                            let hash = self.hash_node(&leaf)?;
-                                            self.node_store.insert(hash, leaf.clone());
-                                            println!("Collapsed to leaf: {:?}", leaf);
-                                            Ok((leaf, deleted_value))
-                                        }
-                                        Node::Branch {
-                                            prefix: child_prefix,
-                                            children: child_children,
-                                            value: child_value,
-                                        } => {
-                                            // Create a new branch with the combined prefix
-                                            let mut new_prefix = prefix.as_ref().to_vec();
-                                            new_prefix.push(remaining_nibble);
-                                            new_prefix.extend_from_slice(child_prefix.as_ref());
-                                            let branch = Node::Branch {
-                                                prefix: new_prefix.into(),
-                                                children: child_children,
-                                                value: child_value,
-                                            };
-                                            let hash = self.hash_node(&branch)?;
-                                            self.node_store.insert(hash, branch.clone());
-                                            println!("Collapsed to branch: {:?}", branch);
-                                            Ok((branch, deleted_value))
-                                        }
-                                        Node::Empty => Ok((Node::Empty, deleted_value)),
-                                    }
-                                } else {
-                                    println!(
-                                        "Multiple children remain or has value, keeping branch"
-                                    );
-                                    // Multiple children remain or has value, keep the branch
-                                    let branch = Node::Branch {
-                                        prefix,
-                                        children,
-                                        value,
-                                    };
-                                    let hash = self.hash_node(&branch)?;
-                                    self.node_store.insert(hash, branch.clone());
-                                    println!("Kept branch: {:?}", branch);
-                                    Ok((branch, deleted_value))
-                                }
-                            }
-                            _ => {
-                                println!("Child was not deleted, putting it back");
-                                // Child was not deleted or was modified, put it back
-                                children.insert(child_nibble, Box::new(new_child));
-                                let branch = Node::Branch {
-                                    prefix,
-                                    children,
-                                    value,
-                                };
-                                let hash = self.hash_node(&branch)?;
-                                self.node_store.insert(hash, branch.clone());
-                                println!("Updated branch: {:?}", branch);
-                                Ok((branch, deleted_value))
-                            }
-                        }
-                    } else {
-                        Ok((
-                            Node::Branch {
-                                prefix,
-                                children,
-                                value,
-                            },
-                            None,
-                        ))
-                    }
+            }
+            Node::Extension { prefix, child } => {
+                let mut path = path;
+                path.extend_from_slice(prefix.as_ref());
+                Self::collect_entries(child, path, entries);
+            }
+            Node::Branch { children, value } => {
+                if let Some(value) = value {
+                    entries.push((path.clone().into(), value));
+                }
+                for (nibble, child) in children.iter() {
+                    let mut child_path = path.clone();
+                    child_path.push(nibble);
+                    Self::collect_entries(child, child_path, entries);
                 }
             }
         }
@@ -561,53 +304,80 @@ where
 
     /// Computes the cryptographic hash of the entire trie
     ///
+    /// In [`HashMode::Ethereum`] the hash is computed over the key-value
+    /// pairs of the trie and does not depend on `hash_node`. It is cached
+    /// until the next insertion or deletion.
+    ///
     /// # Returns
     /// * `Ok(Vec<u8>)` containing the root hash
     /// * `Err(TrieError)` if hashing fails
     pub fn root_hash(&self) -> Result<Vec<u8>> {
-        self.hash_node(&self.root)
+        match self.mode {
+            HashMode::Native => self.hash_node(&self.root),
+            HashMode::Ethereum => Ok(self.eth_trie().root_hash().to_vec()),
+        }
     }
 
-    /// Internal method to recursively compute node hashes
-    #[allow(clippy::only_used_in_recursion)]
+    /// Returns the trie encoded as in [`HashMode::Ethereum`]
+    ///
+    /// The encoding is built over the key-value pairs on first use and
+    /// reused until the next insertion or deletion.
+    pub(crate) fn eth_trie(&self) -> &EthTrie {
+        self.eth_trie.get_or_init(|| {
+            EthTrie::new(
+                self.iter()
+                    .map(|(key, value)| (key.as_ref().to_vec(), value.as_ref())),
+            )
+        })
+    }
+
+    /// Fails with [`TrieError::UnsupportedHashMode`] unless the trie uses
+    /// `mode`
+    ///
+    /// Proofs and witnesses are built from the node encodings of one mode
+    /// and only verify against root hashes of that mode.
+    pub(crate) fn require_mode(&self, mode: HashMode) -> Result<()> {
+        if self.mode != mode {
+            return Err(TrieError::UnsupportedHashMode(self.mode));
+        }
+        Ok(())
+    }
+
+    /// Computes the hash of a node, the hash of its encoding
     pub fn hash_node(&self, node: &Node<K, Option<V>>) -> Result<Vec<u8>> {
-        println!("Hashing node: {:?}", node);
+        Ok(hash_data(&self.encode_node(node)?))
+    }
+
+    /// Computes the reference to a node from its parent
+    ///
+    /// Nodes whose encoding is shorter than the hash length are embedded
+    /// in their parent, any other node is referenced by its hash.
+    pub fn node_reference(&self, node: &Node<K, Option<V>>) -> Result<Vec<u8>> {
+        Ok(node_reference(self.encode_node(node)?))
+    }
+
+    /// Internal method to recursively encode nodes
+    pub fn encode_node(&self, node: &Node<K, Option<V>>) -> Result<Vec<u8>> {
         match node {
-            Node::Empty => Ok(hash_empty()),
-            Node::Leaf { key, value } => {
-                let key_nibbles = to_nibbles(key.as_ref())?;
-                println!(
-                    "Leaf node - key: {:?}, nibbles: {:?}, value: {:?}",
-                    key, key_nibbles, value
-                );
-                hash_leaf(
-                    &key_nibbles,
+            Node::Empty => Ok(encode_empty()),
+            Node::Leaf { path, value } => {
+                let path_nibbles = to_nibbles(path.as_ref())?;
+                Ok(encode_leaf(
+                    &path_nibbles,
                     value.as_ref().map(|v| v.as_ref()).unwrap_or(&[]),
-                )
+                ))
             }
-            Node::Branch {
-                prefix,
-                children,
-                value,
-            } => {
+            Node::Extension { prefix, child } => {
                 let prefix_nibbles = to_nibbles(prefix.as_ref())?;
-                println!(
-                    "Branch node - prefix: {:?}, nibbles: {:?}, children: {:?}, value: {:?}",
-                    prefix, prefix_nibbles, children, value
-                );
-                let child_hashes = children
+                encode_extension(&prefix_nibbles, &self.node_reference(child)?)
+            }
+            Node::Branch { children, value } => {
+                let child_refs = children
                     .iter()
-                    .map(|(k, child)| {
-                        println!("Processing child with key: {:?}", k);
-                        Ok((*k, self.hash_node(child)?))
-                    })
+                    .map(|(k, child)| Ok((k, self.node_reference(child)?)))
                     .collect::<Result<Vec<_>>>()?;
 
-                hash_branch(
-                    &prefix_nibbles,
-                    &child_hashes,
-                    value.as_ref().map(|v| v.as_ref()).unwrap_or(&[]),
-                )
+                encode_branch(&child_refs, value.as_ref().map(|v| v.as_ref()))
             }
         }
     }
@@ -624,6 +394,60 @@ where
     }
 }
 
+/// Full tries record every node built by an update in the node store
+impl<K, V> NodeBuilder for PatriciaMerkleTrie<K, V>
+where
+    K: AsRef<[u8]> + Clone + From<Vec<u8>> + std::fmt::Debug,
+    V: Clone + AsRef<[u8]> + std::fmt::Debug,
+{
+    type Node = Node<K, Option<V>>;
+    type Value = V;
+    type Children = Children<K, Option<V>>;
+
+    fn open(node: Self::Node) -> Result<View<Self>> {
+        Ok(match node {
+            // Leaves always hold a value, one without is as good as absent
+            Node::Empty | Node::Leaf { value: None, .. } => NodeView::Empty,
+            Node::Leaf {
+                path,
+                value: Some(value),
+            } => NodeView::Leaf {
+                path: to_nibbles(path.as_ref())?,
+                value,
+            },
+            Node::Extension { prefix, child } => NodeView::Extension {
+                prefix: to_nibbles(prefix.as_ref())?,
+                child,
+            },
+            Node::Branch { children, value } => NodeView::Branch { children, value },
+        })
+    }
+
+    fn close(view: View<Self>) -> Self::Node {
+        match view {
+            NodeView::Empty => Node::Empty,
+            NodeView::Leaf { path, value } => Node::Leaf {
+                path: path.into(),
+                value: Some(value),
+            },
+            NodeView::Extension { prefix, child } => Node::Extension {
+                prefix: prefix.into(),
+                child,
+            },
+            NodeView::Branch { children, value } => Node::Branch { children, value },
+            NodeView::Unrevealed(node) => node,
+        }
+    }
+
+    fn store(&mut self, node: Self::Node) -> Result<Self::Node> {
+        self.store_node(node)
+    }
+
+    fn is_empty(node: &Self::Node) -> bool {
+        node.is_empty()
+    }
+}
+
 //-----------------------------------------------------------------------
 
 #[cfg(test)]
@@ -638,22 +462,135 @@ mod tests {
 
     #[test]
     fn test_insert_at_empty() -> Result<()> {
-        let mut trie = PatriciaMerkleTrie::new();
-        let result = trie.insert_at(Node::Empty, vec![1], vec![1], Some(vec![2]))?;
+        let mut trie: PatriciaMerkleTrie<Vec<u8>, Vec<u8>> = PatriciaMerkleTrie::new();
+        let result = trie.insert_at(Node::Empty, vec![1], vec![2])?;
         assert!(matches!(result, Node::Leaf { .. }));
         Ok(())
     }
 
     #[test]
     fn test_branch_creation() -> Result<()> {
-        let mut trie = PatriciaMerkleTrie::new();
-        let leaf1 = trie.insert_at(Node::Empty, vec![1, 2], vec![1, 2], Some(vec![3]))?;
-        let result = trie.insert_at(leaf1, vec![1, 3], vec![1, 3], Some(vec![4]))?;
+        let mut trie: PatriciaMerkleTrie<Vec<u8>, Vec<u8>> = PatriciaMerkleTrie::new();
+        let leaf1 = trie.insert_at(Node::Empty, vec![1, 2], vec![3])?;
+        let result = trie.insert_at(leaf1, vec![1, 3], vec![4])?;
+
+        // The shared nibble becomes an extension leading to the branch
+        assert!(matches!(result, Node::Extension { .. }));
+        if let Node::Extension { prefix, child } = result {
+            assert_eq!(prefix, vec![1]);
+            assert!(matches!(*child, Node::Branch { ref children, .. } if children.len() == 2));
+        }
 
+        // Without a shared nibble the branch is the top node
+        let leaf3 = trie.insert_at(Node::Empty, vec![2], vec![5])?;
+        let result = trie.insert_at(leaf3, vec![3], vec![6])?;
         assert!(matches!(result, Node::Branch { .. }));
-        if let Node::Branch { children, .. } = result {
-            assert_eq!(children.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_leaves_store_suffix() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(b"example.com".to_vec(), vec![1])?;
+        trie.insert(b"example.org".to_vec(), vec![2])?;
+
+        // Only the nibbles below the branch remain in the leaves
+        match &trie.root {
+            Node::Extension { prefix, child } => {
+                assert_eq!(prefix, b"example.");
+                match child.as_ref() {
+                    Node::Branch { children, .. } => {
+                        assert!(
+                            matches!(children.get(b'c'), Some(Node::Leaf { path, .. }) if path == b"om")
+                        );
+                        assert!(
+                            matches!(children.get(b'o'), Some(Node::Leaf { path, .. }) if path == b"rg")
+                        );
+                    }
+                    _ => panic!("extension must point at a branch"),
+                }
+            }
+            _ => panic!("expected extension root"),
+        }
+
+        // A lone leaf at the root holds the whole key
+        trie.delete(&b"example.org".to_vec())?;
+        assert!(matches!(&trie.root, Node::Leaf { path, .. } if path == b"example.com"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_small_nodes_inlined() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(vec![1, 2], vec![1])?;
+        trie.insert(vec![1, 3], vec![2])?;
+
+        // Tiny leaves below the branch are embedded in it and never stored
+        let is_child_leaf = |node: &&Node<Vec<u8>, Option<Vec<u8>>>| matches!(node, Node::Leaf { path, .. } if path.is_empty());
+        assert!(!trie.node_store.values().any(|n| is_child_leaf(&n)));
+        let leaf = Node::Leaf {
+            path: vec![],
+            value: Some(vec![1]),
+        };
+        assert_eq!(trie.node_reference(&leaf)?, trie.encode_node(&leaf)?);
+
+        // Leaves with larger values are referenced by hash and stored
+        trie.insert(vec![1, 4], vec![3; 32])?;
+        let stored: Vec<_> = trie.node_store.values().filter(is_child_leaf).collect();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(trie.node_reference(stored[0])?, trie.hash_node(stored[0])?);
+
+        // The root is always stored, however small
+        let mut single = PatriciaMerkleTrie::new();
+        single.insert(vec![1], vec![1])?;
+        assert!(single.node_store.contains_key(&single.root_hash()?));
+
+        // Ethereum mode never reads the native hashes and stores nothing
+        let mut ethereum = PatriciaMerkleTrie::with_mode(HashMode::Ethereum);
+        ethereum.insert(vec![1, 2], vec![1; 40])?;
+        ethereum.insert(vec![1, 3], vec![2; 40])?;
+        ethereum.delete(&vec![1, 2])?;
+        assert!(ethereum.node_store.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extension_split_and_merge() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(vec![1, 2, 3, 4], vec![1])?;
+        trie.insert(vec![1, 2, 3, 5], vec![2])?;
+        assert!(
+            matches!(trie.root, Node::Extension { ref prefix, .. } if *prefix == vec![1, 2, 3])
+        );
+
+        // Diverging inside the extension splits it around a new branch
+        trie.insert(vec![1, 7], vec![3])?;
+        match &trie.root {
+            Node::Extension { prefix, child } => {
+                assert_eq!(*prefix, vec![1]);
+                match child.as_ref() {
+                    Node::Branch { children, .. } => {
+                        assert!(children.get(2).unwrap().is_extension());
+                        assert!(children.get(7).unwrap().is_leaf());
+                    }
+                    _ => panic!("extension must point at a branch"),
+                }
+            }
+            _ => panic!("expected extension root"),
         }
+
+        // Deleting the diverging key merges the extensions again
+        let before = {
+            let mut fresh = PatriciaMerkleTrie::new();
+            fresh.insert(vec![1, 2, 3, 4], vec![1])?;
+            fresh.insert(vec![1, 2, 3, 5], vec![2])?;
+            fresh.root_hash()?
+        };
+        trie.delete(&vec![1, 7])?;
+        assert!(
+            matches!(trie.root, Node::Extension { ref prefix, .. } if *prefix == vec![1, 2, 3])
+        );
+        assert_eq!(trie.root_hash()?, before);
         Ok(())
     }
 
@@ -703,12 +640,6 @@ mod tests {
     fn test_invalid_operations() {
         let mut trie = PatriciaMerkleTrie::new();
 
-        // Test empty key
-        assert!(matches!(
-            trie.insert(vec![], vec![1]),
-            Err(TrieError::InvalidKey)
-        ));
-
         // Test long key
         assert!(matches!(
             trie.insert(vec![0; 33], vec![1]),
@@ -716,18 +647,37 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_empty_key_at_root() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(vec![], vec![0])?;
+        assert!(matches!(trie.root, Node::Leaf { .. }));
+
+        // Other keys turn the root into a branch holding the empty key value
+        trie.insert(vec![1, 2], vec![1])?;
+        trie.insert(vec![1, 3], vec![2])?;
+        assert!(matches!(trie.root, Node::Branch { value: Some(_), .. }));
+        assert_eq!(trie.get(&vec![])?, Some(&vec![0]));
+        assert_eq!(trie.get(&vec![1, 2])?, Some(&vec![1]));
+
+        assert_eq!(trie.delete(&vec![])?, Some(vec![0]));
+        assert_eq!(trie.get(&vec![])?, None);
+        assert_eq!(trie.get(&vec![1, 3])?, Some(&vec![2]));
+        Ok(())
+    }
+
     #[test]
     fn test_hash_consistency() -> Result<()> {
         let trie = PatriciaMerkleTrie::new(); // Removed mut since we don't modify the trie
 
         // Same content should produce same hash
         let hash1 = trie.hash_node(&Node::Leaf {
-            key: vec![1],
+            path: vec![1],
             value: Some(vec![2]),
         })?;
 
         let hash2 = trie.hash_node(&Node::Leaf {
-            key: vec![1],
+            path: vec![1],
             value: Some(vec![2]),
         })?;
 
@@ -741,12 +691,12 @@ mod tests {
 
         // Same content should produce same hash
         let hash1 = trie.hash_node(&Node::Leaf {
-            key: vec![1],
+            path: vec![1],
             value: Some(vec![2]),
         })?;
 
         let hash2 = trie.hash_node(&Node::Leaf {
-            key: vec![1],
+            path: vec![1],
             value: Some(vec![2]),
         })?;
 
@@ -14,42 +14,97 @@ pub fn common_prefix(a: &[u8], b: &[u8]) -> usize {
 
 /// Convert a byte slice to a vector of nibbles (4-bit values)
 pub fn to_nibbles(bytes: &[u8]) -> Result<Vec<u8>> {
-    println!("Converting bytes to nibbles: {:?}", bytes);
     // Return empty vector for empty input
     if bytes.is_empty() {
-        println!("Empty input, returning empty vector");
         return Ok(Vec::new());
     }
 
     // For prefix bytes, they are already in nibble form
     // Just convert them directly to a vector
     let result = bytes.to_vec();
-    println!("Converted to nibbles: {:?}", result);
     Ok(result)
 }
 
 /// Verifies if a key is valid for use in the trie
 ///
+/// The empty key is valid and addresses the value at the root of the trie.
+///
 /// # Arguments
 /// * `key` - Byte slice to verify
 ///
 /// # Returns
 /// Result indicating if the key is valid
 pub fn verify_key(key: &[u8]) -> Result<()> {
-    println!("Verifying key: {:?}", key); // Debug print
-    if key.is_empty() {
-        println!("Key is empty"); // Debug print
-        return Err(TrieError::InvalidKey);
-    }
     if key.len() > 32 {
-        println!("Key is too long: length = {}", key.len()); // Debug print
         return Err(TrieError::KeyTooLong); // Corrected error type
     }
-    println!("Key is valid: {:?}", key); // Debug print
     Ok(())
 }
 
-/// Helper function to format nibbles for debugging
+/// Splits bytes into 4-bit nibbles, high nibble first
+///
+/// Unlike [`to_nibbles`], which keeps one key byte per path step, this
+/// produces the hexary paths used by Ethereum tries.
+pub fn to_hex_nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+/// Encodes a nibble path in hex-prefix (compact) form
+///
+/// The first nibble of the output holds flags: bit 1 is set for a leaf
+/// path and bit 0 for a path of odd length. An odd path stores its first
+/// nibble next to the flags, an even path pads the flags with a zero
+/// nibble, and the remaining nibbles are packed two per byte.
+///
+/// # Arguments
+/// * `nibbles` - Path to encode, each value below 16
+/// * `is_leaf` - Whether the path ends in a leaf rather than an extension
+///
+/// # Returns
+/// The packed path, `nibbles.len() / 2 + 1` bytes long
+pub fn hex_prefix_encode(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+    let flag = if is_leaf { 2 } else { 0 };
+    let mut encoded = Vec::with_capacity(nibbles.len() / 2 + 1);
+    let rest = if nibbles.len() % 2 == 1 {
+        encoded.push(((flag + 1) << 4) | nibbles[0]);
+        &nibbles[1..]
+    } else {
+        encoded.push(flag << 4);
+        nibbles
+    };
+    for pair in rest.chunks(2) {
+        encoded.push((pair[0] << 4) | pair[1]);
+    }
+    encoded
+}
+
+/// Decodes a hex-prefix (compact) encoded path
+///
+/// # Arguments
+/// * `encoded` - Path produced by [`hex_prefix_encode`]
+///
+/// # Returns
+/// * `Ok((Vec<u8>, bool))` - The nibbles of the path and whether it is a leaf path
+/// * `Err(TrieError::InvalidPrefix)` - If the input is empty, has unknown
+///   flags or a non-zero padding nibble
+pub fn hex_prefix_decode(encoded: &[u8]) -> Result<(Vec<u8>, bool)> {
+    let (&first, rest) = encoded.split_first().ok_or(TrieError::InvalidPrefix)?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err(TrieError::InvalidPrefix);
+    }
+
+    let mut nibbles = Vec::with_capacity(rest.len() * 2 + 1);
+    if flag & 1 == 1 {
+        nibbles.push(first & 0x0f);
+    } else if first & 0x0f != 0 {
+        return Err(TrieError::InvalidPrefix);
+    }
+    nibbles.extend(to_hex_nibbles(rest));
+    Ok((nibbles, flag & 2 == 2))
+}
+
+// Helper function to format nibbles for debugging
 //pub(crate) fn format_nibbles(nibbles: &[u8]) -> String {
 //    nibbles
 //        .iter()
@@ -81,11 +136,59 @@ mod tests {
 
     #[test]
     fn test_verify_key() {
-        assert!(verify_key(&[]).is_err());
+        assert!(verify_key(&[]).is_ok());
         assert!(verify_key(&[0; 33]).is_err());
         assert!(verify_key(&[1, 2, 3]).is_ok());
     }
 
+    #[test]
+    fn test_to_hex_nibbles() {
+        assert_eq!(to_hex_nibbles(&[]), vec![]);
+        assert_eq!(to_hex_nibbles(&[0x12, 0xab]), vec![1, 2, 10, 11]);
+    }
+
+    #[test]
+    fn test_hex_prefix_encode() {
+        assert_eq!(
+            hex_prefix_encode(&[1, 2, 3, 4, 5], false),
+            vec![0x11, 0x23, 0x45]
+        );
+        assert_eq!(
+            hex_prefix_encode(&[0, 1, 2, 3, 4, 5], false),
+            vec![0x00, 0x01, 0x23, 0x45]
+        );
+        assert_eq!(
+            hex_prefix_encode(&[0, 15, 1, 12, 11, 8], true),
+            vec![0x20, 0x0f, 0x1c, 0xb8]
+        );
+        assert_eq!(
+            hex_prefix_encode(&[15, 1, 12, 11, 8], true),
+            vec![0x3f, 0x1c, 0xb8]
+        );
+        assert_eq!(hex_prefix_encode(&[], true), vec![0x20]);
+        assert_eq!(hex_prefix_encode(&[], false), vec![0x00]);
+    }
+
+    #[test]
+    fn test_hex_prefix_round_trip() -> Result<()> {
+        for path in [vec![], vec![7], vec![1, 2], vec![0, 15, 1, 12, 11, 8, 3]] {
+            for is_leaf in [false, true] {
+                let encoded = hex_prefix_encode(&path, is_leaf);
+                assert_eq!(hex_prefix_decode(&encoded)?, (path.clone(), is_leaf));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_hex_prefix_decode_invalid() {
+        assert!(hex_prefix_decode(&[]).is_err());
+        // Unknown flags
+        assert!(hex_prefix_decode(&[0x40]).is_err());
+        // Even path with a non-zero padding nibble
+        assert!(hex_prefix_decode(&[0x21, 0x23]).is_err());
+    }
+
     #[test]
     fn test_simple_nibbles() -> Result<()> {
         let input = vec![0x1];
@@ -0,0 +1,1591 @@
+//! Proof verification without the standard library.
+//!
+//! Holds everything needed to check any proof of the trie against a root
+//! hash, be it a [`MerkleProof`], an [`ExclusionProof`], a [`MultiProof`],
+//! a [`RangeProof`] or a [`PrefixProof`]: the node hash, the decoding of
+//! node encodings and of the binary proof format, and the walks along the
+//! proven paths. It needs nothing beyond `alloc`, so with the default `std`
+//! feature disabled the crate builds for `no_std` targets such as embedded
+//! firmware and exposes only this module.
+//!
+//! Each key byte is one step of the path, as in the trie.
+//!
+//! [`BatchVerifier`] verifies many proofs against the same root, hashing
+//! the nodes they share once.
+//!
+//! [`verify_proof`] answers whether a proof holds. When it does not,
+//! [`check_proof`] tells which step failed, with the hashes involved, to
+//! track down proofs from other implementations that do not verify.
+//!
+//! [`MerkleProof`]: crate::verify::MerkleProof
+//! [`ExclusionProof`]: crate::verify::ExclusionProof
+//! [`MultiProof`]: crate::verify::MultiProof
+//! [`RangeProof`]: crate::verify::RangeProof
+//! [`PrefixProof`]: crate::verify::PrefixProof
+//! [`verify_proof`]: crate::verify::verify_proof
+//! [`check_proof`]: crate::verify::check_proof
+//! [`BatchVerifier`]: crate::verify::BatchVerifier
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::fmt;
+use sha2::{Digest, Sha256};
+
+/// Errors of proof verification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The proof is malformed, or has nodes missing or left over
+    InvalidProof,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidProof => f.write_str("Invalid proof"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VerifyError {}
+
+/// Result of proof verification
+pub type Result<T> = core::result::Result<T, VerifyError>;
+
+/// Step at which a proof failed to verify
+///
+/// Hashes are SHA-256 hashes as used by the trie. Node indices refer to
+/// the `nodes` of the proof, such as [`MerkleProof::nodes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    /// The fields of the proof do not fit together, such as a multi-proof
+    /// without one value per key
+    MalformedProof,
+    /// The proof lists no node for the given index, where the path to the
+    /// key continues
+    MissingNode { index: usize },
+    /// A proof whose nodes are looked up by hash has no node with the given
+    /// hash, where a path continues
+    MissingChild { hash: Vec<u8> },
+    /// The node at the given index cannot be decoded, or holds a malformed
+    /// embedded node
+    MalformedNode { index: usize },
+    /// The node at the given index is listed before, in a proof whose
+    /// nodes are looked up by hash
+    DuplicateNode { index: usize },
+    /// The node at the given index is not needed to reach the result
+    UnusedNode { index: usize },
+    /// The first node does not hash to the root hash
+    ///
+    /// For proofs whose nodes are looked up by hash, no node hashes to the
+    /// root hash and `computed` is the hash of the first node.
+    RootMismatch {
+        expected: Vec<u8>,
+        computed: Vec<u8>,
+    },
+    /// The node at the given index does not hash to the reference its
+    /// parent holds
+    NodeMismatch {
+        index: usize,
+        expected: Vec<u8>,
+        computed: Vec<u8>,
+    },
+    /// The key holds another value than the proof claims
+    ///
+    /// Holds the hash of the claimed value and of the value the nodes
+    /// show, `None` standing for no value.
+    ValueMismatch {
+        expected: Option<Vec<u8>>,
+        computed: Option<Vec<u8>>,
+    },
+    /// The entries of a range or prefix proof differ from those the nodes
+    /// hold, first at the given index
+    ///
+    /// Holds the key of that entry in the claimed and the computed entries,
+    /// `None` standing for the end of the entries. When only the values
+    /// differ, [`ProofError::ValueMismatch`] is reported instead.
+    EntryMismatch {
+        index: usize,
+        expected: Option<Vec<u8>>,
+        computed: Option<Vec<u8>>,
+    },
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MalformedProof => f.write_str("Proof fields are inconsistent"),
+            Self::MissingNode { index } => write!(f, "Proof node {} is missing", index),
+            Self::MissingChild { hash } => write!(f, "Proof has no node with hash {}", Hex(hash)),
+            Self::MalformedNode { index } => write!(f, "Proof node {} is malformed", index),
+            Self::DuplicateNode { index } => write!(f, "Proof node {} is listed twice", index),
+            Self::UnusedNode { index } => write!(f, "Proof node {} is not on the path", index),
+            Self::RootMismatch { expected, computed } => write!(
+                f,
+                "Root hash mismatch: expected {}, computed {}",
+                Hex(expected),
+                Hex(computed)
+            ),
+            Self::NodeMismatch {
+                index,
+                expected,
+                computed,
+            } => write!(
+                f,
+                "Hash mismatch at proof node {}: expected {}, computed {}",
+                index,
+                Hex(expected),
+                Hex(computed)
+            ),
+            Self::ValueMismatch { expected, computed } => write!(
+                f,
+                "Value hash mismatch: expected {}, computed {}",
+                OptionHex(expected),
+                OptionHex(computed)
+            ),
+            Self::EntryMismatch {
+                index,
+                expected,
+                computed,
+            } => write!(
+                f,
+                "Entry {} mismatch: expected key {}, computed key {}",
+                index,
+                OptionHex(expected),
+                OptionHex(computed)
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ProofError {}
+
+impl From<ProofError> for VerifyError {
+    fn from(_: ProofError) -> Self {
+        VerifyError::InvalidProof
+    }
+}
+
+/// Formats bytes as lowercase hex
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+/// Formats optional bytes as hex, or as `none`
+struct OptionHex<'a>(&'a Option<Vec<u8>>);
+
+impl fmt::Display for OptionHex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(hash) => Hex(hash).fmt(f),
+            None => f.write_str("none"),
+        }
+    }
+}
+
+/// Length of a node hash, and the length from which children are
+/// referenced by hash instead of embedded in their parent
+pub const HASH_LEN: usize = 32;
+
+/// Computes a SHA-256 hash of arbitrary data
+///
+/// # Arguments
+/// * `data` - Byte slice to hash
+///
+/// # Returns
+/// A vector containing the 32-byte hash
+pub fn hash_data(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize().to_vec()
+}
+
+/// Appends a length as a 4-byte big-endian integer
+pub(crate) fn push_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u32).to_be_bytes());
+}
+
+/// Appends a field prefixed with its length
+pub(crate) fn push_field(out: &mut Vec<u8>, field: &[u8]) {
+    push_len(out, field.len());
+    out.extend_from_slice(field);
+}
+
+/// Appends a list of fields prefixed with their count
+pub(crate) fn push_fields(out: &mut Vec<u8>, fields: &[Vec<u8>]) {
+    push_len(out, fields.len());
+    for field in fields {
+        push_field(out, field);
+    }
+}
+
+/// A node decoded from its encoding, with children as references
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodedNode {
+    /// A leaf with the remaining key nibbles and its value
+    Leaf { path: Vec<u8>, value: Vec<u8> },
+    /// An extension with its prefix and the reference to its branch
+    Extension { prefix: Vec<u8>, child: Vec<u8> },
+    /// A branch with its (nibble, reference) children and optional value
+    Branch {
+        children: Vec<(u8, Vec<u8>)>,
+        value: Option<Vec<u8>>,
+    },
+    /// The empty trie
+    Empty,
+}
+
+/// Reads the fields of an encoded node
+pub(crate) struct NodeReader<'a> {
+    pub(crate) data: &'a [u8],
+}
+
+impl<'a> NodeReader<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(VerifyError::InvalidProof);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn len(&mut self) -> Result<usize> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    pub(crate) fn field(&mut self) -> Result<&'a [u8]> {
+        let len = self.len()?;
+        self.take(len)
+    }
+
+    /// Reads the version byte that starts the binary proof format
+    pub(crate) fn version(&mut self) -> Result<()> {
+        if self.byte()? != PROOF_FORMAT_VERSION {
+            return Err(VerifyError::InvalidProof);
+        }
+        Ok(())
+    }
+
+    /// Reads the count of a list whose items take at least `min_len` bytes
+    ///
+    /// Counts larger than the rest of the input could hold are rejected
+    /// before anything is allocated for them.
+    pub(crate) fn count(&mut self, min_len: usize) -> Result<usize> {
+        let count = self.len()?;
+        if count > self.data.len() / min_len {
+            return Err(VerifyError::InvalidProof);
+        }
+        Ok(count)
+    }
+
+    /// Reads a list of fields written by `push_fields`
+    pub(crate) fn fields(&mut self) -> Result<Vec<Vec<u8>>> {
+        let count = self.count(4)?;
+        (0..count).map(|_| Ok(self.field()?.to_vec())).collect()
+    }
+
+    /// Checks that the whole input was read
+    pub(crate) fn finish(&self) -> Result<()> {
+        if !self.data.is_empty() {
+            return Err(VerifyError::InvalidProof);
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a node encoding produced by the `encode_*` functions
+///
+/// Decoding is strict: it rejects anything the encoders cannot produce,
+/// so every node has a single valid encoding.
+///
+/// # Arguments
+/// * `encoded` - The encoded node
+///
+/// # Returns
+/// * `Ok(DecodedNode)` - The decoded node
+/// * `Err(VerifyError::InvalidProof)` - If the encoding is truncated, has
+///   trailing bytes, an unknown tag, or describes an invalid node
+pub fn decode_node(encoded: &[u8]) -> Result<DecodedNode> {
+    let mut reader = NodeReader { data: encoded };
+    let node = match reader.byte()? {
+        0x00 => {
+            let path = reader.field()?.to_vec();
+            let value = reader.field()?.to_vec();
+            DecodedNode::Leaf { path, value }
+        }
+        0x01 => {
+            let count = reader.len()?;
+            let mut children = Vec::new();
+            for _ in 0..count {
+                let nibble = reader.byte()?;
+                children.push((nibble, reader.field()?.to_vec()));
+            }
+            let value = match reader.byte()? {
+                0x00 => None,
+                0x01 => Some(reader.field()?.to_vec()),
+                _ => return Err(VerifyError::InvalidProof),
+            };
+            let sorted = children.windows(2).all(|pair| pair[0].0 < pair[1].0);
+            if children.is_empty() || !sorted {
+                return Err(VerifyError::InvalidProof);
+            }
+            DecodedNode::Branch { children, value }
+        }
+        0x02 => DecodedNode::Empty,
+        0x03 => {
+            let prefix = reader.field()?.to_vec();
+            let child = reader.field()?.to_vec();
+            if prefix.is_empty() {
+                return Err(VerifyError::InvalidProof);
+            }
+            DecodedNode::Extension { prefix, child }
+        }
+        _ => return Err(VerifyError::InvalidProof),
+    };
+    if !reader.data.is_empty() {
+        return Err(VerifyError::InvalidProof);
+    }
+    Ok(node)
+}
+
+/// Proof that a key holds a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MerkleProof {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    /// Encodings of the nodes on the path to the key, root first
+    pub nodes: Vec<Vec<u8>>,
+}
+
+/// Version of the binary proof format written by [`MerkleProof::to_bytes`]
+/// and the `to_bytes` methods of the other proofs
+pub const PROOF_FORMAT_VERSION: u8 = 1;
+
+impl MerkleProof {
+    /// Encodes the proof in the binary proof format
+    ///
+    /// The format is the version byte followed by the key, the value and
+    /// the number of nodes, then each node. Keys, values, nodes and the
+    /// count are prefixed with their length as a 4-byte big-endian integer.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let nodes_len: usize = self.nodes.iter().map(|node| node.len() + 4).sum();
+        let mut out = Vec::with_capacity(13 + self.key.len() + self.value.len() + nodes_len);
+        out.push(PROOF_FORMAT_VERSION);
+        push_field(&mut out, &self.key);
+        push_field(&mut out, &self.value);
+        push_fields(&mut out, &self.nodes);
+        out
+    }
+
+    /// Decodes a proof written by [`MerkleProof::to_bytes`]
+    ///
+    /// # Arguments
+    /// * `data` - The encoded proof
+    ///
+    /// # Returns
+    /// * `Ok(MerkleProof)` - The decoded proof, not yet verified
+    /// * `Err(VerifyError::InvalidProof)` - If the version is unknown, a length
+    ///   runs past the end of the input, or bytes are left over
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = NodeReader { data };
+        reader.version()?;
+        let key = reader.field()?.to_vec();
+        let value = reader.field()?.to_vec();
+        let nodes = reader.fields()?;
+        reader.finish()?;
+        Ok(Self { key, value, nodes })
+    }
+}
+
+/// Proof that a key is not in the trie
+///
+/// Lists the nodes on the path to the key up to where the path leaves it:
+/// the branch without a child for the key's next nibble, the extension
+/// whose prefix diverges from the key, or the leaf holding another key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExclusionProof {
+    pub key: Vec<u8>,
+    /// Encodings of the nodes on the path to the key, root first
+    pub nodes: Vec<Vec<u8>>,
+}
+
+impl ExclusionProof {
+    /// Encodes the proof in the binary proof format
+    ///
+    /// The format is the version byte followed by the key and the nodes,
+    /// laid out as in [`MerkleProof::to_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let nodes_len: usize = self.nodes.iter().map(|node| node.len() + 4).sum();
+        let mut out = Vec::with_capacity(9 + self.key.len() + nodes_len);
+        out.push(PROOF_FORMAT_VERSION);
+        push_field(&mut out, &self.key);
+        push_fields(&mut out, &self.nodes);
+        out
+    }
+
+    /// Decodes a proof written by [`ExclusionProof::to_bytes`]
+    ///
+    /// # Returns
+    /// * `Ok(ExclusionProof)` - The decoded proof, not yet verified
+    /// * `Err(VerifyError::InvalidProof)` - If the version is unknown, a length
+    ///   runs past the end of the input, or bytes are left over
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = NodeReader { data };
+        reader.version()?;
+        let key = reader.field()?.to_vec();
+        let nodes = reader.fields()?;
+        reader.finish()?;
+        Ok(Self { key, nodes })
+    }
+}
+
+/// Proof for several keys, present or absent, against one root
+///
+/// `values[i]` is the value of `keys[i]`, or `None` if the key is not in
+/// the trie. Nodes shared between the paths of the keys are listed once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MultiProof {
+    pub keys: Vec<Vec<u8>>,
+    pub values: Vec<Option<Vec<u8>>>,
+    /// Encodings of the nodes on the paths to the keys, each listed once
+    pub nodes: Vec<Vec<u8>>,
+}
+
+/// Proof of all entries with keys in `start..end`
+///
+/// `entries` are sorted by key. The nodes include the paths to both bounds,
+/// so an empty range is proven too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RangeProof {
+    pub start: Vec<u8>,
+    pub end: Vec<u8>,
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Encodings of the nodes whose subtrees overlap the range, each listed once
+    pub nodes: Vec<Vec<u8>>,
+}
+
+/// Proof of all entries with keys starting with `prefix`
+///
+/// `entries` are sorted by key. The nodes include the path to the prefix,
+/// so a prefix without any keys is proven too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrefixProof {
+    pub prefix: Vec<u8>,
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Encodings of the nodes on the path to the prefix and below it, each
+    /// listed once
+    pub nodes: Vec<Vec<u8>>,
+}
+
+impl MultiProof {
+    /// Encodes the proof in the binary proof format
+    ///
+    /// The format is the version byte, the number of keys, then each key
+    /// followed by a presence byte, 0x01 with the value after it or 0x00
+    /// for an absent key, and finally the nodes. Lengths and counts are
+    /// 4-byte big-endian integers, as in [`MerkleProof::to_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![PROOF_FORMAT_VERSION];
+        push_len(&mut out, self.keys.len());
+        for (key, value) in self.keys.iter().zip(&self.values) {
+            push_field(&mut out, key);
+            match value {
+                Some(value) => {
+                    out.push(0x01);
+                    push_field(&mut out, value);
+                }
+                None => out.push(0x00),
+            }
+        }
+        push_fields(&mut out, &self.nodes);
+        out
+    }
+
+    /// Decodes a proof written by [`MultiProof::to_bytes`]
+    ///
+    /// # Returns
+    /// * `Ok(MultiProof)` - The decoded proof, not yet verified
+    /// * `Err(VerifyError::InvalidProof)` - If the version is unknown, a
+    ///   length runs past the end of the input, a presence byte is neither
+    ///   0x00 nor 0x01, or bytes are left over
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = NodeReader { data };
+        reader.version()?;
+        let count = reader.count(5)?;
+        let mut keys = Vec::with_capacity(count);
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            keys.push(reader.field()?.to_vec());
+            values.push(match reader.byte()? {
+                0x00 => None,
+                0x01 => Some(reader.field()?.to_vec()),
+                _ => return Err(VerifyError::InvalidProof),
+            });
+        }
+        let nodes = reader.fields()?;
+        reader.finish()?;
+        Ok(Self {
+            keys,
+            values,
+            nodes,
+        })
+    }
+}
+
+impl RangeProof {
+    /// Encodes the proof in the binary proof format
+    ///
+    /// The format is the version byte, the bounds, the number of entries,
+    /// then each entry's key and value, and finally the nodes, laid out as
+    /// in [`MerkleProof::to_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![PROOF_FORMAT_VERSION];
+        push_field(&mut out, &self.start);
+        push_field(&mut out, &self.end);
+        push_entries(&mut out, &self.entries);
+        push_fields(&mut out, &self.nodes);
+        out
+    }
+
+    /// Decodes a proof written by [`RangeProof::to_bytes`]
+    ///
+    /// # Returns
+    /// * `Ok(RangeProof)` - The decoded proof, not yet verified
+    /// * `Err(VerifyError::InvalidProof)` - If the version is unknown, a length
+    ///   runs past the end of the input, or bytes are left over
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = NodeReader { data };
+        reader.version()?;
+        let start = reader.field()?.to_vec();
+        let end = reader.field()?.to_vec();
+        let entries = read_entries(&mut reader)?;
+        let nodes = reader.fields()?;
+        reader.finish()?;
+        Ok(Self {
+            start,
+            end,
+            entries,
+            nodes,
+        })
+    }
+}
+
+impl PrefixProof {
+    /// Encodes the proof in the binary proof format
+    ///
+    /// The format is the version byte, the prefix, the entries and the
+    /// nodes, laid out as in [`RangeProof::to_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![PROOF_FORMAT_VERSION];
+        push_field(&mut out, &self.prefix);
+        push_entries(&mut out, &self.entries);
+        push_fields(&mut out, &self.nodes);
+        out
+    }
+
+    /// Decodes a proof written by [`PrefixProof::to_bytes`]
+    ///
+    /// # Returns
+    /// * `Ok(PrefixProof)` - The decoded proof, not yet verified
+    /// * `Err(VerifyError::InvalidProof)` - If the version is unknown, a length
+    ///   runs past the end of the input, or bytes are left over
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = NodeReader { data };
+        reader.version()?;
+        let prefix = reader.field()?.to_vec();
+        let entries = read_entries(&mut reader)?;
+        let nodes = reader.fields()?;
+        reader.finish()?;
+        Ok(Self {
+            prefix,
+            entries,
+            nodes,
+        })
+    }
+}
+
+/// Appends key-value entries prefixed with their count
+fn push_entries(out: &mut Vec<u8>, entries: &[(Vec<u8>, Vec<u8>)]) {
+    push_len(out, entries.len());
+    for (key, value) in entries {
+        push_field(out, key);
+        push_field(out, value);
+    }
+}
+
+/// Reads key-value entries written by `push_entries`
+fn read_entries(reader: &mut NodeReader) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let count = reader.count(8)?;
+    (0..count)
+        .map(|_| Ok((reader.field()?.to_vec(), reader.field()?.to_vec())))
+        .collect()
+}
+
+/// Verifies a proof that a key holds a value
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie
+/// * `proof` - The proof to verify
+///
+/// # Returns
+/// * `Ok(true)` - If the proof shows that the key holds the claimed value
+/// * `Ok(false)` - If the nodes do not hash to the root, or the key holds
+///   another value or none
+/// * `Err(VerifyError::InvalidProof)` - If the proof has no nodes, a node
+///   is malformed, or nodes are missing or left over
+pub fn verify_proof(root_hash: &[u8], proof: &MerkleProof) -> Result<bool> {
+    outcome(check_proof(root_hash, proof))
+}
+
+/// Verifies a proof that a key is not in the trie
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie
+/// * `proof` - The proof to verify
+///
+/// # Returns
+/// * `Ok(true)` - If the proof shows that the key holds no value
+/// * `Ok(false)` - If the nodes do not hash to the root, or the key holds
+///   a value
+/// * `Err(VerifyError::InvalidProof)` - As for [`verify_proof`]
+pub fn verify_exclusion_proof(root_hash: &[u8], proof: &ExclusionProof) -> Result<bool> {
+    outcome(check_exclusion_proof(root_hash, proof))
+}
+
+/// Verifies a proof for several keys
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie
+/// * `proof` - The proof to verify
+///
+/// # Returns
+/// * `Ok(true)` - If the proof shows that every key holds its claimed
+///   value, or is absent where no value is claimed
+/// * `Ok(false)` - If no node hashes to the root, or a key holds another
+///   value than claimed
+/// * `Err(VerifyError::InvalidProof)` - If the proof has no keys or not one
+///   value per key, a node is malformed or listed twice, or nodes are
+///   missing or left over
+pub fn verify_multi_proof(root_hash: &[u8], proof: &MultiProof) -> Result<bool> {
+    outcome(check_multi_proof(root_hash, proof))
+}
+
+/// Verifies a proof of all entries with keys in a range
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie
+/// * `proof` - The proof to verify
+///
+/// # Returns
+/// * `Ok(true)` - If the proof shows that the trie holds exactly its
+///   entries in `start..end`
+/// * `Ok(false)` - If no node hashes to the root, or the entries differ
+///   from those the nodes hold
+/// * `Err(VerifyError::InvalidProof)` - If a node is malformed or listed
+///   twice, or nodes are missing or left over
+pub fn verify_range_proof(root_hash: &[u8], proof: &RangeProof) -> Result<bool> {
+    outcome(check_range_proof(root_hash, proof))
+}
+
+/// Verifies a proof of all entries with keys starting with a prefix
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie
+/// * `proof` - The proof to verify
+///
+/// # Returns
+/// * `Ok(true)` - If the proof shows that the trie holds exactly its
+///   entries under the prefix
+/// * `Ok(false)` - As for [`verify_range_proof`]
+/// * `Err(VerifyError::InvalidProof)` - As for [`verify_range_proof`]
+pub fn verify_prefix_proof(root_hash: &[u8], proof: &PrefixProof) -> Result<bool> {
+    outcome(check_prefix_proof(root_hash, proof))
+}
+
+/// Checks a proof that a key holds a value, reporting which step failed
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie
+/// * `proof` - The proof to check
+///
+/// # Returns
+/// * `Ok(())` - If the proof shows that the key holds the claimed value
+/// * `Err(ProofError)` - The first step of the verification that failed
+pub fn check_proof(root_hash: &[u8], proof: &MerkleProof) -> core::result::Result<(), ProofError> {
+    let found = lookup(root_hash, &proof.key, &proof.nodes, |_, node| {
+        hash_data(node)
+    })?;
+    check_value(Some(&proof.value), found)
+}
+
+/// Checks a proof that a key is not in the trie, reporting which step
+/// failed
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie
+/// * `proof` - The proof to check
+///
+/// # Returns
+/// * `Ok(())` - If the proof shows that the key holds no value
+/// * `Err(ProofError)` - The first step of the verification that failed
+pub fn check_exclusion_proof(
+    root_hash: &[u8],
+    proof: &ExclusionProof,
+) -> core::result::Result<(), ProofError> {
+    let found = lookup(root_hash, &proof.key, &proof.nodes, |_, node| {
+        hash_data(node)
+    })?;
+    check_value(None, found)
+}
+
+/// Checks a multi-proof, reporting which step failed
+///
+/// Keys are checked in order, and the first failure is reported.
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie
+/// * `proof` - The proof to check
+///
+/// # Returns
+/// * `Ok(())` - If the proof shows that every key holds its claimed value,
+///   or is absent where no value is claimed
+/// * `Err(ProofError)` - The first step of the verification that failed
+pub fn check_multi_proof(
+    root_hash: &[u8],
+    proof: &MultiProof,
+) -> core::result::Result<(), ProofError> {
+    if proof.keys.is_empty() || proof.keys.len() != proof.values.len() {
+        return Err(ProofError::MalformedProof);
+    }
+
+    let mut index = NodeIndex::new(&proof.nodes)?;
+    for (key, value) in proof.keys.iter().zip(&proof.values) {
+        let mut fetched = 0;
+        let mut failure = None;
+        let result = walk(root_hash, key, |hash| match index.fetch_indexed(hash) {
+            Some((position, node)) => {
+                fetched = position;
+                Ok(Some(node))
+            }
+            None => {
+                failure = Some(missing_node(root_hash, &proof.nodes, hash));
+                Err(VerifyError::InvalidProof)
+            }
+        });
+        let found = match result {
+            Ok(Lookup::Found(found)) => Some(found),
+            Ok(Lookup::Absent) => None,
+            // Anything else fails while decoding the last node fetched
+            Ok(Lookup::HashMismatch) | Err(_) => {
+                return Err(failure.unwrap_or(ProofError::MalformedNode { index: fetched }))
+            }
+        };
+        check_value(value.as_deref(), found)?;
+    }
+
+    // Every node must be on the path of some key
+    index.check_all_used()
+}
+
+/// Checks a range proof, reporting which step failed
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie
+/// * `proof` - The proof to check
+///
+/// # Returns
+/// * `Ok(())` - If the proof shows that the trie holds exactly its entries
+///   in `start..end`
+/// * `Err(ProofError)` - The first step of the verification that failed
+pub fn check_range_proof(
+    root_hash: &[u8],
+    proof: &RangeProof,
+) -> core::result::Result<(), ProofError> {
+    let keys = KeySet::Range {
+        start: &proof.start,
+        end: &proof.end,
+    };
+    check_key_set(root_hash, &proof.nodes, &keys, &proof.entries)
+}
+
+/// Checks a prefix proof, reporting which step failed
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie
+/// * `proof` - The proof to check
+///
+/// # Returns
+/// * `Ok(())` - If the proof shows that the trie holds exactly its entries
+///   with keys starting with the prefix
+/// * `Err(ProofError)` - The first step of the verification that failed
+pub fn check_prefix_proof(
+    root_hash: &[u8],
+    proof: &PrefixProof,
+) -> core::result::Result<(), ProofError> {
+    check_key_set(
+        root_hash,
+        &proof.nodes,
+        &KeySet::Prefix(&proof.prefix),
+        &proof.entries,
+    )
+}
+
+/// Reports a node with the given hash that a proof does not list
+///
+/// Without the root node the proof is for another root.
+fn missing_node(root_hash: &[u8], nodes: &[Vec<u8>], hash: &[u8]) -> ProofError {
+    if hash != root_hash {
+        return ProofError::MissingChild {
+            hash: hash.to_vec(),
+        };
+    }
+    match nodes.first() {
+        Some(first) => ProofError::RootMismatch {
+            expected: root_hash.to_vec(),
+            computed: hash_data(first),
+        },
+        None => ProofError::MissingNode { index: 0 },
+    }
+}
+
+/// Nodes of a proof looked up by hash instead of by position
+pub(crate) struct NodeIndex<'a> {
+    /// Position and encoding of each node, by hash
+    by_hash: BTreeMap<Vec<u8>, (usize, &'a [u8])>,
+    /// Whether each node has been fetched, by position
+    used: Vec<bool>,
+}
+
+impl<'a> NodeIndex<'a> {
+    /// Indexes the nodes, failing if one is listed twice
+    pub(crate) fn new(nodes: &'a [Vec<u8>]) -> core::result::Result<Self, ProofError> {
+        let mut by_hash = BTreeMap::new();
+        for (index, node) in nodes.iter().enumerate() {
+            if by_hash
+                .insert(hash_data(node), (index, node.as_slice()))
+                .is_some()
+            {
+                return Err(ProofError::DuplicateNode { index });
+            }
+        }
+        Ok(Self {
+            by_hash,
+            used: vec![false; nodes.len()],
+        })
+    }
+
+    /// Returns the encoding of the node with the given hash
+    #[cfg(feature = "std")]
+    pub(crate) fn fetch(&mut self, hash: &[u8]) -> Option<Vec<u8>> {
+        self.fetch_indexed(hash).map(|(_, node)| node)
+    }
+
+    /// Returns the position and encoding of the node with the given hash
+    fn fetch_indexed(&mut self, hash: &[u8]) -> Option<(usize, Vec<u8>)> {
+        let &(index, node) = self.by_hash.get(hash)?;
+        self.used[index] = true;
+        Some((index, node.to_vec()))
+    }
+
+    /// Whether every node has been fetched
+    #[cfg(feature = "std")]
+    pub(crate) fn all_used(&self) -> bool {
+        self.used.iter().all(|&used| used)
+    }
+
+    /// Fails with the first node that has not been fetched
+    fn check_all_used(&self) -> core::result::Result<(), ProofError> {
+        match self.used.iter().position(|&used| !used) {
+            Some(index) => Err(ProofError::UnusedNode { index }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checks the entries of a range or prefix proof
+///
+/// # Returns
+/// * `Ok(())` - If the nodes below the root hold exactly `entries` in the
+///   set of keys
+/// * `Err(ProofError)` - If a node is malformed or listed twice, a subtree
+///   overlapping the set is missing, a node does not overlap the set, or
+///   the entries differ
+fn check_key_set(
+    root_hash: &[u8],
+    nodes: &[Vec<u8>],
+    keys: &KeySet,
+    entries: &[(Vec<u8>, Vec<u8>)],
+) -> core::result::Result<(), ProofError> {
+    let mut index = NodeIndex::new(nodes)?;
+    let Some((position, root)) = index.fetch_indexed(root_hash) else {
+        return Err(missing_node(root_hash, nodes, root_hash));
+    };
+    let mut found = Vec::new();
+    walk_range(&mut index, position, root, Vec::new(), keys, &mut found)?;
+
+    // Every node must overlap the set
+    index.check_all_used()?;
+    check_entries(entries, &found)
+}
+
+/// Compares the claimed entries of a range or prefix proof with the ones
+/// the nodes hold
+fn check_entries(
+    expected: &[(Vec<u8>, Vec<u8>)],
+    computed: &[(Vec<u8>, Vec<u8>)],
+) -> core::result::Result<(), ProofError> {
+    let index = match expected.iter().zip(computed).position(|(a, b)| a != b) {
+        Some(index) => index,
+        None if expected.len() == computed.len() => return Ok(()),
+        None => expected.len().min(computed.len()),
+    };
+    match (expected.get(index), computed.get(index)) {
+        (Some((key, expected)), Some((found, computed))) if key == found => {
+            Err(ProofError::ValueMismatch {
+                expected: Some(hash_data(expected)),
+                computed: Some(hash_data(computed)),
+            })
+        }
+        (expected, computed) => Err(ProofError::EntryMismatch {
+            index,
+            expected: expected.map(|(key, _)| key.clone()),
+            computed: computed.map(|(key, _)| key.clone()),
+        }),
+    }
+}
+
+/// Collects the entries in a set of keys below a node of a range or prefix
+/// proof
+///
+/// `position` is the index of the node in the proof, or of the listed node
+/// it is embedded in.
+///
+/// # Returns
+/// * `Ok(())` - Entries are appended in key order
+/// * `Err(ProofError)` - If a node is malformed or a subtree overlapping
+///   the set is not in the proof
+fn walk_range(
+    index: &mut NodeIndex,
+    position: usize,
+    encoded: Vec<u8>,
+    mut path: Vec<u8>,
+    keys: &KeySet,
+    entries: &mut Vec<(Vec<u8>, Vec<u8>)>,
+) -> core::result::Result<(), ProofError> {
+    // Embedded children are their own encoding, others must be in the proof
+    let descend = |index: &mut NodeIndex, child: Vec<u8>, path, entries: &mut _| {
+        if child.len() < HASH_LEN {
+            return walk_range(index, position, child, path, keys, entries);
+        }
+        match index.fetch_indexed(&child) {
+            Some((position, node)) => walk_range(index, position, node, path, keys, entries),
+            None => Err(ProofError::MissingChild { hash: child }),
+        }
+    };
+
+    let malformed = ProofError::MalformedNode { index: position };
+    match decode_node(&encoded).map_err(|_| malformed.clone())? {
+        DecodedNode::Empty if path.is_empty() => {}
+        DecodedNode::Empty => return Err(malformed),
+        DecodedNode::Leaf {
+            path: suffix,
+            value,
+        } => {
+            path.extend(suffix);
+            if keys.contains(&path) {
+                entries.push((path, value));
+            }
+        }
+        DecodedNode::Extension { prefix, child } => {
+            path.extend(prefix);
+            if keys.overlaps(&path) {
+                descend(index, child, path, entries)?;
+            }
+        }
+        DecodedNode::Branch { children, value } => {
+            if let Some(value) = value {
+                if keys.contains(&path) {
+                    entries.push((path.clone(), value));
+                }
+            }
+            for (nibble, child) in children {
+                let mut child_path = path.clone();
+                child_path.push(nibble);
+                if keys.overlaps(&child_path) {
+                    descend(index, child, child_path, entries)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Keys covered by a range or prefix proof
+pub(crate) enum KeySet<'a> {
+    /// Keys in `start..end`
+    Range { start: &'a [u8], end: &'a [u8] },
+    /// Keys starting with the prefix
+    Prefix(&'a [u8]),
+}
+
+impl KeySet<'_> {
+    /// Whether `key` is in the set
+    pub(crate) fn contains(&self, key: &[u8]) -> bool {
+        match self {
+            Self::Range { start, end } => *start <= key && key < *end,
+            Self::Prefix(prefix) => key.starts_with(prefix),
+        }
+    }
+
+    /// Whether keys starting with `path` can be in the set
+    pub(crate) fn overlaps(&self, path: &[u8]) -> bool {
+        match self {
+            // The smallest such key is `path` itself. A path below `start`
+            // only leads to keys in the range if `start` extends it.
+            Self::Range { start, end } => {
+                path < *end && (path >= *start || start.starts_with(path))
+            }
+            Self::Prefix(prefix) => path.starts_with(prefix) || prefix.starts_with(path),
+        }
+    }
+}
+
+/// Verifier for many proofs against the same root
+///
+/// Remembers the nodes whose hash it has checked, so nodes shared between
+/// proofs, such as the root and the branches near it, are hashed once. The
+/// last node of each proof, usually the leaf of its key, is not
+/// remembered, which keeps the cache to the upper levels of the trie. The
+/// cache only grows, so use one verifier per root and drop it once the
+/// root changes.
+#[derive(Debug, Clone)]
+pub struct BatchVerifier {
+    root_hash: Vec<u8>,
+    /// Encodings of checked nodes, by their hash
+    verified: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl BatchVerifier {
+    /// Creates a verifier for proofs against `root_hash`
+    pub fn new(root_hash: Vec<u8>) -> Self {
+        BatchVerifier {
+            root_hash,
+            verified: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the root hash proofs are verified against
+    pub fn root_hash(&self) -> &[u8] {
+        &self.root_hash
+    }
+
+    /// Returns the number of nodes whose hash is remembered
+    pub fn cached_nodes(&self) -> usize {
+        self.verified.len()
+    }
+
+    /// Verifies a proof that a key holds a value, as [`verify_proof`] does
+    pub fn verify_proof(&mut self, proof: &MerkleProof) -> Result<bool> {
+        outcome(self.check_proof(proof))
+    }
+
+    /// Verifies a proof that a key is not in the trie, as
+    /// [`verify_exclusion_proof`] does
+    pub fn verify_exclusion_proof(&mut self, proof: &ExclusionProof) -> Result<bool> {
+        outcome(self.check_exclusion_proof(proof))
+    }
+
+    /// Checks a proof that a key holds a value, as [`check_proof`] does
+    pub fn check_proof(&mut self, proof: &MerkleProof) -> core::result::Result<(), ProofError> {
+        let found = self.lookup(&proof.key, &proof.nodes)?;
+        check_value(Some(&proof.value), found)
+    }
+
+    /// Checks a proof that a key is not in the trie, as
+    /// [`check_exclusion_proof`] does
+    pub fn check_exclusion_proof(
+        &mut self,
+        proof: &ExclusionProof,
+    ) -> core::result::Result<(), ProofError> {
+        let found = self.lookup(&proof.key, &proof.nodes)?;
+        check_value(None, found)
+    }
+
+    /// Follows a key through the nodes of a proof, hashing only the nodes
+    /// not checked before
+    fn lookup(
+        &mut self,
+        nibbles: &[u8],
+        nodes: &[Vec<u8>],
+    ) -> core::result::Result<Option<Vec<u8>>, ProofError> {
+        let verified = &self.verified;
+        let mut checked = Vec::new();
+        let found = lookup(&self.root_hash, nibbles, nodes, |hash, node| {
+            if verified.get(hash).is_some_and(|known| known == node) {
+                return hash.to_vec();
+            }
+            let computed = hash_data(node);
+            if computed == hash {
+                checked.push((computed.clone(), node.to_vec()));
+            }
+            computed
+        });
+
+        // A node that hashes to its reference stays valid whatever the
+        // rest of the proof holds
+        let last = nodes.last().map(Vec::as_slice);
+        for (hash, node) in checked {
+            if Some(node.as_slice()) != last {
+                self.verified.insert(hash, node);
+            }
+        }
+        found
+    }
+}
+
+/// Compares the claimed value of a key with the one the proof shows
+pub(crate) fn check_value(
+    expected: Option<&[u8]>,
+    computed: Option<Vec<u8>>,
+) -> core::result::Result<(), ProofError> {
+    if expected == computed.as_deref() {
+        return Ok(());
+    }
+    Err(ProofError::ValueMismatch {
+        expected: expected.map(hash_data),
+        computed: computed.as_deref().map(hash_data),
+    })
+}
+
+/// Turns the outcome of a check into whether the proof holds, keeping
+/// errors for malformed proofs
+pub(crate) fn outcome(checked: core::result::Result<(), ProofError>) -> Result<bool> {
+    match checked {
+        Ok(()) => Ok(true),
+        Err(
+            ProofError::RootMismatch { .. }
+            | ProofError::NodeMismatch { .. }
+            | ProofError::ValueMismatch { .. }
+            | ProofError::EntryMismatch { .. },
+        ) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Outcome of following a key through the nodes of a proof
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Lookup {
+    /// The key holds this value
+    Found(Vec<u8>),
+    /// The path to the key ends before reaching it
+    Absent,
+    /// A node does not hash to the root or to its parent's reference
+    HashMismatch,
+}
+
+/// Follows `nibbles` from the root through the nodes of a proof
+///
+/// # Returns
+/// * `Ok(Option<Vec<u8>>)` - The value the proof shows for the key, if any
+/// * `Err(ProofError)` - The step at which the nodes fail to lead to the
+///   key, including nodes left over once the lookup is complete
+///
+/// `hash_node` returns the hash of a node's encoding, given the hash the
+/// node is expected to have.
+fn lookup<H>(
+    root_hash: &[u8],
+    nibbles: &[u8],
+    nodes: &[Vec<u8>],
+    mut hash_node: H,
+) -> core::result::Result<Option<Vec<u8>>, ProofError>
+where
+    H: FnMut(&[u8], &[u8]) -> Vec<u8>,
+{
+    // Nodes are listed in path order, each must hash to the reference it
+    // replaces. The first failure is kept to report it.
+    let mut index = 0;
+    let mut failure = None;
+    let result = walk(root_hash, nibbles, |hash| {
+        let Some(next) = nodes.get(index) else {
+            failure = Some(ProofError::MissingNode { index });
+            return Err(VerifyError::InvalidProof);
+        };
+        index += 1;
+        let computed = hash_node(hash, next);
+        if computed == hash {
+            return Ok(Some(next.clone()));
+        }
+        failure = Some(match index - 1 {
+            0 => ProofError::RootMismatch {
+                expected: hash.to_vec(),
+                computed,
+            },
+            index => ProofError::NodeMismatch {
+                index,
+                expected: hash.to_vec(),
+                computed,
+            },
+        });
+        Ok(None)
+    });
+
+    let found = match result {
+        Ok(Lookup::Found(value)) => Some(value),
+        Ok(Lookup::Absent) => None,
+        Ok(Lookup::HashMismatch) | Err(_) => {
+            // Anything else fails while decoding the last node fetched
+            return Err(failure.unwrap_or(ProofError::MalformedNode {
+                index: index.saturating_sub(1),
+            }));
+        }
+    };
+
+    // Every node must have been used to reach the result
+    if index < nodes.len() {
+        return Err(ProofError::UnusedNode { index });
+    }
+    Ok(found)
+}
+
+/// Follows `nibbles` from the root, fetching hashed nodes from `fetch`
+///
+/// `fetch` returns the encoding of the node with the given hash, `None` if
+/// the node it has does not match the hash, or an error if it has none.
+pub(crate) fn walk<F>(root_hash: &[u8], nibbles: &[u8], mut fetch: F) -> Result<Lookup>
+where
+    F: FnMut(&[u8]) -> Result<Option<Vec<u8>>>,
+{
+    let mut pos = 0;
+    let mut encoded = match fetch(root_hash)? {
+        Some(root) => root,
+        None => return Ok(Lookup::HashMismatch),
+    };
+
+    loop {
+        let child = match decode_node(&encoded)? {
+            DecodedNode::Empty if pos == 0 => return Ok(Lookup::Absent),
+            DecodedNode::Empty => return Err(VerifyError::InvalidProof),
+            DecodedNode::Leaf { path, value } => {
+                if nibbles[pos..] == path[..] {
+                    return Ok(Lookup::Found(value));
+                }
+                return Ok(Lookup::Absent);
+            }
+            DecodedNode::Extension { prefix, child } => {
+                if !nibbles[pos..].starts_with(&prefix) {
+                    return Ok(Lookup::Absent);
+                }
+                pos += prefix.len();
+                child
+            }
+            DecodedNode::Branch { children, value } => {
+                let Some(&nibble) = nibbles.get(pos) else {
+                    return Ok(value.map_or(Lookup::Absent, Lookup::Found));
+                };
+                match children.into_iter().find(|(n, _)| *n == nibble) {
+                    Some((_, child)) => {
+                        pos += 1;
+                        child
+                    }
+                    None => return Ok(Lookup::Absent),
+                }
+            }
+        };
+
+        // Embedded children are their own encoding, others are fetched by hash
+        encoded = if child.len() < HASH_LEN {
+            child
+        } else {
+            match fetch(&child)? {
+                Some(next) => next,
+                None => return Ok(Lookup::HashMismatch),
+            }
+        };
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{ExtendedProofTrait, MerkleProofTrait},
+        PatriciaMerkleTrie, TrieError,
+    };
+
+    type Trie = PatriciaMerkleTrie<Vec<u8>, Vec<u8>>;
+
+    fn build() -> Trie {
+        let mut trie = PatriciaMerkleTrie::new();
+        for key in [&b"a"[..], b"ab", b"car", b"cart", b"dog"] {
+            trie.insert(key.to_vec(), vec![key[0]; 40]).unwrap();
+        }
+        trie
+    }
+
+    #[test]
+    fn test_verify_proof() {
+        let trie = build();
+        let root = trie.root_hash().unwrap();
+        let proof = trie.generate_proof(&b"cart".to_vec()).unwrap();
+
+        // Only the root and the bytes of the proof are needed
+        let decoded = MerkleProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(verify_proof(&root, &decoded), Ok(true));
+        assert_eq!(verify_proof(&[0; 32], &decoded), Ok(false));
+
+        let mut wrong_value = decoded.clone();
+        wrong_value.value = vec![0; 40];
+        assert_eq!(verify_proof(&root, &wrong_value), Ok(false));
+
+        let mut truncated = decoded;
+        truncated.nodes.pop();
+        assert_eq!(
+            verify_proof(&root, &truncated),
+            Err(VerifyError::InvalidProof)
+        );
+    }
+
+    #[test]
+    fn test_verify_exclusion_proof() {
+        let trie = build();
+        let root = trie.root_hash().unwrap();
+        let proof = trie.generate_exclusion_proof(&b"cat".to_vec()).unwrap();
+        assert_eq!(verify_exclusion_proof(&root, &proof), Ok(true));
+
+        let mut present = proof;
+        present.key = b"cart".to_vec();
+        assert_ne!(verify_exclusion_proof(&root, &present), Ok(true));
+    }
+
+    #[test]
+    fn test_verify_multi_range_and_prefix_proofs() {
+        let trie = build();
+        let root = trie.root_hash().unwrap();
+
+        // Only the root and the bytes of the proofs are needed
+        let multi = trie
+            .generate_multi_proof(&[b"car".to_vec(), b"cat".to_vec()])
+            .unwrap();
+        let multi = MultiProof::from_bytes(&multi.to_bytes()).unwrap();
+        assert_eq!(verify_multi_proof(&root, &multi), Ok(true));
+        assert_eq!(check_multi_proof(&root, &multi), Ok(()));
+
+        let range = trie
+            .generate_range_proof(&b"b".to_vec(), &b"d".to_vec())
+            .unwrap();
+        let range = RangeProof::from_bytes(&range.to_bytes()).unwrap();
+        assert_eq!(range.entries.len(), 2);
+        assert_eq!(verify_range_proof(&root, &range), Ok(true));
+
+        let prefix = trie.generate_prefix_proof(&b"a".to_vec()).unwrap();
+        let prefix = PrefixProof::from_bytes(&prefix.to_bytes()).unwrap();
+        assert_eq!(prefix.entries.len(), 2);
+        assert_eq!(verify_prefix_proof(&root, &prefix), Ok(true));
+
+        let mut omitted = prefix;
+        omitted.entries.pop();
+        assert_eq!(verify_prefix_proof(&root, &omitted), Ok(false));
+        assert!(matches!(
+            check_prefix_proof(&root, &omitted),
+            Err(ProofError::EntryMismatch {
+                index: 1,
+                expected: None,
+                ..
+            })
+        ));
+        assert_eq!(verify_range_proof(&[0; 32], &range), Ok(false));
+    }
+
+    #[test]
+    fn test_check_proof_reports_failed_step() {
+        let trie = build();
+        let root = trie.root_hash().unwrap();
+        let proof = trie.generate_proof(&b"cart".to_vec()).unwrap();
+        assert!(proof.nodes.len() > 2);
+        assert_eq!(check_proof(&root, &proof), Ok(()));
+
+        assert_eq!(
+            check_proof(&[0; 32], &proof),
+            Err(ProofError::RootMismatch {
+                expected: vec![0; 32],
+                computed: root.clone(),
+            })
+        );
+
+        let mut tampered = proof.clone();
+        tampered.nodes[1][6] ^= 1;
+        assert!(matches!(
+            check_proof(&root, &tampered),
+            Err(ProofError::NodeMismatch { index: 1, expected, computed })
+                if expected == hash_data(&proof.nodes[1])
+                    && computed == hash_data(&tampered.nodes[1])
+        ));
+
+        let mut wrong_value = proof.clone();
+        wrong_value.value = vec![0; 40];
+        assert_eq!(
+            check_proof(&root, &wrong_value),
+            Err(ProofError::ValueMismatch {
+                expected: Some(hash_data(&[0; 40])),
+                computed: Some(hash_data(&proof.value)),
+            })
+        );
+        let mut absent = proof.clone();
+        absent.key = b"carts".to_vec();
+        assert!(matches!(
+            check_proof(&root, &absent),
+            Err(ProofError::ValueMismatch { computed: None, .. })
+        ));
+
+        let mut truncated = proof.clone();
+        let last = truncated.nodes.len() - 1;
+        truncated.nodes.pop();
+        assert_eq!(
+            check_proof(&root, &truncated),
+            Err(ProofError::MissingNode { index: last })
+        );
+
+        let mut extra = proof.clone();
+        extra.nodes.push(vec![0x02]);
+        assert_eq!(
+            check_proof(&root, &extra),
+            Err(ProofError::UnusedNode { index: last + 1 })
+        );
+
+        let malformed = vec![0x05; 40];
+        let mut broken = proof;
+        broken.nodes = vec![malformed.clone()];
+        assert_eq!(
+            check_proof(&hash_data(&malformed), &broken),
+            Err(ProofError::MalformedNode { index: 0 })
+        );
+    }
+
+    #[test]
+    fn test_check_exclusion_proof() {
+        let trie = build();
+        let root = trie.root_hash().unwrap();
+        let proof = trie.generate_exclusion_proof(&b"cat".to_vec()).unwrap();
+        assert_eq!(check_exclusion_proof(&root, &proof), Ok(()));
+
+        let mut present = trie.generate_proof(&b"cart".to_vec()).unwrap();
+        let value = core::mem::take(&mut present.value);
+        let present = ExclusionProof {
+            key: present.key,
+            nodes: present.nodes,
+        };
+        assert_eq!(
+            check_exclusion_proof(&root, &present),
+            Err(ProofError::ValueMismatch {
+                expected: None,
+                computed: Some(hash_data(&value)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_batch_verifier_matches_single_proofs() {
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..64u8 {
+            trie.insert(vec![i % 4, i], vec![i; 40]).unwrap();
+        }
+        let root = trie.root_hash().unwrap();
+        let proofs: Vec<_> = (0..64u8)
+            .map(|i| trie.generate_proof(&vec![i % 4, i]).unwrap())
+            .collect();
+
+        let mut batch = BatchVerifier::new(root.clone());
+        for proof in &proofs {
+            assert_eq!(batch.verify_proof(proof), Ok(true));
+        }
+        // Only the root and the branches below it are remembered
+        assert_eq!(batch.cached_nodes(), 5);
+        for proof in &proofs {
+            assert_eq!(batch.verify_proof(proof), verify_proof(&root, proof));
+        }
+        assert_eq!(batch.cached_nodes(), 5);
+
+        let exclusion = trie.generate_exclusion_proof(&vec![1, 2]).unwrap();
+        assert_eq!(batch.verify_exclusion_proof(&exclusion), Ok(true));
+        assert_eq!(batch.check_exclusion_proof(&exclusion), Ok(()));
+    }
+
+    #[test]
+    fn test_batch_verifier_rejects_tampered_proofs() {
+        let trie = build();
+        let root = trie.root_hash().unwrap();
+        let proof = trie.generate_proof(&b"cart".to_vec()).unwrap();
+        let mut batch = BatchVerifier::new(root.clone());
+        assert_eq!(batch.verify_proof(&proof), Ok(true));
+        assert!(batch.cached_nodes() > 0);
+
+        // Cached nodes are matched by their encoding, not just their hash
+        for index in 0..proof.nodes.len() {
+            let mut tampered = proof.clone();
+            tampered.nodes[index][6] ^= 1;
+            assert_eq!(batch.check_proof(&tampered), check_proof(&root, &tampered));
+            assert_eq!(batch.verify_proof(&tampered), Ok(false));
+        }
+
+        let mut wrong_value = proof.clone();
+        wrong_value.value = vec![0; 40];
+        assert_eq!(batch.verify_proof(&wrong_value), Ok(false));
+        let mut truncated = proof.clone();
+        truncated.nodes.pop();
+        assert_eq!(
+            batch.verify_proof(&truncated),
+            Err(VerifyError::InvalidProof)
+        );
+
+        let mut other = BatchVerifier::new(vec![0; 32]);
+        assert_eq!(other.verify_proof(&proof), Ok(false));
+        assert_eq!(other.cached_nodes(), 0);
+    }
+
+    #[test]
+    fn test_proof_error_messages() {
+        let err = ProofError::NodeMismatch {
+            index: 2,
+            expected: vec![0xab, 0x01],
+            computed: vec![0x00],
+        };
+        assert_eq!(
+            err.to_string(),
+            "Hash mismatch at proof node 2: expected ab01, computed 00"
+        );
+        let err = ProofError::ValueMismatch {
+            expected: Some(vec![0x0f]),
+            computed: None,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Value hash mismatch: expected 0f, computed none"
+        );
+        assert_eq!(
+            VerifyError::from(ProofError::MalformedNode { index: 0 }),
+            VerifyError::InvalidProof
+        );
+    }
+
+    #[test]
+    fn test_error_converts_to_trie_error() {
+        let err: TrieError = VerifyError::InvalidProof.into();
+        assert!(matches!(err, TrieError::InvalidProof));
+        assert_eq!(VerifyError::InvalidProof.to_string(), err.to_string());
+    }
+}
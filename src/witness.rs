@@ -0,0 +1,384 @@
+//! Witnesses for state transitions.
+//!
+//! A [`Witness`] holds a batch of operations together with the nodes of
+//! the trie they read or restructure. Anyone who knows the root hash before
+//! the batch can rebuild the affected part of the trie from the witness,
+//! apply the batch to it and compute the root hash after the batch, without
+//! holding the rest of the trie.
+//!
+//! Besides the paths to the keys of the batch, a witness holds the siblings
+//! that a deletion merges into their parent once their branch collapses.
+//! Witnesses use the native node encoding of [`HashMode::Native`], and
+//! generating one for a trie in another mode fails with
+//! [`TrieError::UnsupportedHashMode`].
+//!
+//! [`Witness::to_bytes`] writes a witness in the binary format of the
+//! proofs, starting with [`PROOF_FORMAT_VERSION`], so it can be sent to
+//! the clients that apply it.
+//!
+//! [`Witness`]: crate::witness::Witness
+//! [`Witness::to_bytes`]: crate::witness::Witness::to_bytes
+//! [`PROOF_FORMAT_VERSION`]: crate::proof::PROOF_FORMAT_VERSION
+//! [`HashMode::Native`]: crate::HashMode::Native
+//! [`TrieError::UnsupportedHashMode`]: crate::TrieError::UnsupportedHashMode
+
+use std::collections::HashSet;
+
+use crate::{
+    hash::hash_data,
+    partial::PartialNode,
+    utils::{to_nibbles, verify_key},
+    verify::{push_field, push_fields, push_len, NodeReader, PROOF_FORMAT_VERSION},
+    HashMode, PatriciaMerkleTrie, Result, TrieError,
+};
+
+/// Change to apply to a trie
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operation {
+    /// Inserts or updates the value of a key
+    Insert(Vec<u8>, Vec<u8>),
+    /// Deletes a key, if present
+    Delete(Vec<u8>),
+}
+
+/// Batch of operations with the trie nodes needed to apply it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Witness {
+    /// Operations in the order they are applied
+    pub operations: Vec<Operation>,
+    /// Encodings of the nodes the operations need, each listed once
+    pub nodes: Vec<Vec<u8>>,
+}
+
+impl Witness {
+    /// Encodes the witness in the binary proof format
+    ///
+    /// The format is the version byte, the number of operations, then each
+    /// operation as a tag byte, 0x00 for an insert followed by the key and
+    /// the value or 0x01 for a delete followed by the key, and finally the
+    /// nodes. Lengths and counts are 4-byte big-endian integers, as in
+    /// [`MerkleProof::to_bytes`](crate::proof::MerkleProof::to_bytes).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![PROOF_FORMAT_VERSION];
+        push_len(&mut out, self.operations.len());
+        for operation in &self.operations {
+            match operation {
+                Operation::Insert(key, value) => {
+                    out.push(0x00);
+                    push_field(&mut out, key);
+                    push_field(&mut out, value);
+                }
+                Operation::Delete(key) => {
+                    out.push(0x01);
+                    push_field(&mut out, key);
+                }
+            }
+        }
+        push_fields(&mut out, &self.nodes);
+        out
+    }
+
+    /// Decodes a witness written by [`Witness::to_bytes`]
+    ///
+    /// # Returns
+    /// * `Ok(Witness)` - The decoded witness, not yet applied
+    /// * `Err(TrieError::InvalidProof)` - If the version is unknown, a length
+    ///   runs past the end of the input, a tag byte is neither 0x00 nor
+    ///   0x01, or bytes are left over
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = NodeReader { data };
+        reader.version()?;
+        let count = reader.count(5)?;
+        let mut operations = Vec::with_capacity(count);
+        for _ in 0..count {
+            operations.push(match reader.byte()? {
+                0x00 => Operation::Insert(reader.field()?.to_vec(), reader.field()?.to_vec()),
+                0x01 => Operation::Delete(reader.field()?.to_vec()),
+                _ => return Err(TrieError::InvalidProof),
+            });
+        }
+        let nodes = reader.fields()?;
+        reader.finish()?;
+        Ok(Self { operations, nodes })
+    }
+}
+
+impl<K, V> PatriciaMerkleTrie<K, V>
+where
+    K: AsRef<[u8]> + Clone + From<Vec<u8>> + std::fmt::Debug,
+    V: Clone + AsRef<[u8]> + std::fmt::Debug,
+{
+    /// Creates the witness for applying `operations` to the trie
+    ///
+    /// The trie itself is not changed.
+    ///
+    /// # Arguments
+    /// * `operations` - Batch of operations, in the order they are applied
+    ///
+    /// # Returns
+    /// * `Ok(Witness)` - The operations and the nodes needed to apply them
+    /// * `Err(TrieError::UnsupportedHashMode)` - If the trie is not in
+    ///   [`HashMode::Native`]
+    /// * `Err(TrieError)` - If a key is invalid
+    pub fn generate_witness(&self, operations: Vec<Operation>) -> Result<Witness> {
+        self.require_mode(HashMode::Native)?;
+        let root_hash = self.hash_node(self.root())?;
+        let mut nodes = Vec::new();
+        let mut seen = HashSet::new();
+        for operation in &operations {
+            let key = match operation {
+                Operation::Insert(key, _) | Operation::Delete(key) => key,
+            };
+            verify_key(key)?;
+            self.add_witness_path(&to_nibbles(key)?, &mut nodes, &mut seen)?;
+        }
+
+        // Collapsing branches reach siblings off the paths, reveal each one
+        // the batch turns out to need until it applies
+        loop {
+            let root = PartialNode::from_nodes(&root_hash, &nodes)?;
+            match apply(root, &operations) {
+                Ok(_) => break,
+                Err(TrieError::MissingWitness(path)) => {
+                    if !self.add_witness_path(&path, &mut nodes, &mut seen)? {
+                        return Err(TrieError::NodeNotFound);
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Witness { operations, nodes })
+    }
+
+    /// Adds the nodes on the path to `nibbles` that are not yet in the
+    /// witness, returning whether there were any
+    fn add_witness_path(
+        &self,
+        nibbles: &[u8],
+        nodes: &mut Vec<Vec<u8>>,
+        seen: &mut HashSet<Vec<u8>>,
+    ) -> Result<bool> {
+        let mut path = Vec::new();
+        self.collect_proof_nodes(self.root(), nibbles, true, &mut path)?;
+        let count = nodes.len();
+        nodes.extend(path.into_iter().filter(|node| seen.insert(hash_data(node))));
+        Ok(nodes.len() > count)
+    }
+}
+
+/// Applies the operations of a batch to a partial trie
+fn apply(mut root: PartialNode, operations: &[Operation]) -> Result<PartialNode> {
+    for operation in operations {
+        root = match operation {
+            Operation::Insert(key, value) => {
+                verify_key(key)?;
+                root.insert(&to_nibbles(key)?, 0, value.clone())?
+            }
+            Operation::Delete(key) => {
+                verify_key(key)?;
+                root.delete(&to_nibbles(key)?, 0)?.0
+            }
+        };
+    }
+    Ok(root)
+}
+
+/// Computes the root hash after applying a witness's batch
+///
+/// # Arguments
+/// * `root_hash` - Root hash of the trie before the batch
+/// * `witness` - The batch and the nodes it needs
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - Root hash of the trie after the batch
+/// * `Err(TrieError::InvalidProof)` - If the witness does not hold the root
+///   node, or holds malformed nodes or nodes not below the root
+/// * `Err(TrieError::MissingWitness)` - If the batch needs a node that is
+///   not in the witness
+pub fn apply_witness(root_hash: &[u8], witness: &Witness) -> Result<Vec<u8>> {
+    let root = PartialNode::from_nodes(root_hash, &witness.nodes)?;
+    apply(root, &witness.operations)?.root_hash()
+}
+
+/// Verifies that a witness's batch turns one root hash into another
+///
+/// # Arguments
+/// * `old_root` - Root hash of the trie before the batch
+/// * `new_root` - Claimed root hash of the trie after the batch
+/// * `witness` - The batch and the nodes it needs
+///
+/// # Returns
+/// * `Ok(bool)` - Whether applying the batch yields `new_root`
+/// * `Err(TrieError)` - If the witness cannot be applied to `old_root`,
+///   as for [`apply_witness`]
+pub fn verify_witness(old_root: &[u8], new_root: &[u8], witness: &Witness) -> Result<bool> {
+    Ok(apply_witness(old_root, witness)? == new_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::MerkleProofTrait;
+
+    type Trie = PatriciaMerkleTrie<Vec<u8>, Vec<u8>>;
+
+    fn build(entries: &[(&[u8], usize)]) -> Result<Trie> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for (key, len) in entries {
+            trie.insert(key.to_vec(), vec![key[0]; *len])?;
+        }
+        Ok(trie)
+    }
+
+    /// Applies the operations to the trie, checking the witness on the way
+    fn check_transition(trie: &mut Trie, operations: Vec<Operation>) -> Result<Witness> {
+        let old_root = trie.root_hash()?;
+        let witness = trie.generate_witness(operations.clone())?;
+        for operation in operations {
+            match operation {
+                Operation::Insert(key, value) => trie.insert(key, value)?,
+                Operation::Delete(key) => {
+                    trie.delete(&key)?;
+                }
+            }
+        }
+        assert_eq!(apply_witness(&old_root, &witness)?, trie.root_hash()?);
+        assert!(verify_witness(&old_root, &trie.root_hash()?, &witness)?);
+        Ok(witness)
+    }
+
+    #[test]
+    fn test_insert_witness() -> Result<()> {
+        let mut trie = build(&[(b"car", 40), (b"cart", 40), (b"dog", 40), (b"a", 2)])?;
+        check_transition(
+            &mut trie,
+            vec![
+                Operation::Insert(b"cat".to_vec(), vec![1; 40]),
+                Operation::Insert(b"dog".to_vec(), vec![2; 40]),
+                Operation::Insert(b"do".to_vec(), vec![3]),
+            ],
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_witness_reveals_collapsing_sibling() -> Result<()> {
+        let mut trie = build(&[(b"car", 40), (b"cart", 40), (b"dog", 40)])?;
+        let root = trie.root_hash()?;
+        let path_only = trie.generate_proof(&b"dog".to_vec())?.nodes.len();
+
+        // Deleting "dog" leaves the "car" subtree as the only child of the root
+        let witness = check_transition(&mut trie, vec![Operation::Delete(b"dog".to_vec())])?;
+        assert!(witness.nodes.len() > path_only);
+
+        // Without the sibling the batch cannot be applied
+        let mut short = witness.clone();
+        short.nodes.truncate(path_only);
+        assert!(matches!(
+            apply_witness(&root, &short),
+            Err(TrieError::MissingWitness(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_mixed_batch_witness() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..32u8 {
+            trie.insert(vec![i % 4, i], vec![i; 40])?;
+        }
+        check_transition(
+            &mut trie,
+            vec![
+                Operation::Delete(vec![1, 1]),
+                Operation::Delete(vec![1, 5]),
+                Operation::Insert(vec![1, 1, 1], vec![7; 40]),
+                Operation::Delete(vec![2]),
+                Operation::Insert(vec![9], vec![9; 3]),
+                Operation::Delete(vec![3, 3]),
+            ],
+        )?;
+        // Emptying the trie entirely
+        let keys: Vec<_> = trie.iter().map(|(key, _)| key).collect();
+        let operations = keys.into_iter().map(Operation::Delete).collect();
+        check_transition(&mut trie, operations)?;
+        assert!(trie.root().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_witness_bytes_round_trip() -> Result<()> {
+        let trie = build(&[(b"car", 40), (b"cart", 40), (b"dog", 40)])?;
+        let root = trie.root_hash()?;
+        let witness = trie.generate_witness(vec![
+            Operation::Insert(b"cat".to_vec(), vec![1; 40]),
+            Operation::Delete(b"dog".to_vec()),
+        ])?;
+
+        let bytes = witness.to_bytes();
+        assert_eq!(bytes[0], PROOF_FORMAT_VERSION);
+        let decoded = Witness::from_bytes(&bytes)?;
+        assert_eq!(decoded, witness);
+        assert_eq!(
+            apply_witness(&root, &decoded)?,
+            apply_witness(&root, &witness)?
+        );
+
+        // Unknown version, unknown tag, truncated input and trailing bytes
+        let mut version = bytes.clone();
+        version[0] = PROOF_FORMAT_VERSION + 1;
+        assert!(matches!(
+            Witness::from_bytes(&version),
+            Err(TrieError::InvalidProof)
+        ));
+        let mut tag = bytes.clone();
+        tag[5] = 0x02;
+        assert!(Witness::from_bytes(&tag).is_err());
+        for len in 0..bytes.len() {
+            assert!(Witness::from_bytes(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(Witness::from_bytes(&trailing).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_ethereum_mode() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::with_mode(HashMode::Ethereum);
+        trie.insert(b"dog".to_vec(), vec![1; 40])?;
+        let operations = vec![Operation::Insert(b"cat".to_vec(), vec![2; 40])];
+        assert!(matches!(
+            trie.generate_witness(operations),
+            Err(TrieError::UnsupportedHashMode(HashMode::Ethereum))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_witness_rejects_other_roots_and_claims() -> Result<()> {
+        let mut trie = build(&[(b"car", 40), (b"cart", 40), (b"dog", 40)])?;
+        let old_root = trie.root_hash()?;
+        let witness = trie.generate_witness(vec![Operation::Delete(b"cart".to_vec())])?;
+        trie.delete(&b"cart".to_vec())?;
+
+        // A different outcome, another starting root, unrelated nodes
+        assert!(!verify_witness(&old_root, &old_root, &witness)?);
+        assert!(verify_witness(&[0; 32], &trie.root_hash()?, &witness).is_err());
+        let mut extra = witness.clone();
+        extra.nodes.push(vec![0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(apply_witness(&old_root, &extra).is_err());
+
+        // Another batch on the same nodes reaches outside the witness
+        let mut other = witness;
+        other.operations = vec![Operation::Delete(b"dog".to_vec())];
+        assert!(matches!(
+            apply_witness(&old_root, &other),
+            Err(TrieError::MissingWitness(_))
+        ));
+        Ok(())
+    }
+}
@@ -45,12 +45,6 @@ mod error_handling {
     fn test_invalid_keys() {
         let mut trie = PatriciaMerkleTrie::new();
 
-        // Empty key
-        assert!(matches!(
-            trie.insert(vec![], b"value".to_vec()),
-            Err(TrieError::InvalidKey)
-        ));
-
         // Key too long
         let long_key = vec![0u8; 33];
         assert!(matches!(
@@ -89,3 +83,678 @@ mod cryptographic_verification {
         Ok(())
     }
 }
+
+mod shared_prefixes {
+    use super::*;
+
+    fn keys() -> Vec<Vec<u8>> {
+        vec![
+            vec![1, 2],
+            vec![1, 3],
+            vec![1, 3, 5],
+            vec![1],
+            vec![2, 2, 2],
+            vec![2, 2, 3],
+            vec![2, 2],
+        ]
+    }
+
+    #[test]
+    fn test_keys_sharing_prefixes() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for (i, key) in keys().into_iter().enumerate() {
+            trie.insert(key, vec![i as u8])?;
+        }
+        for (i, key) in keys().iter().enumerate() {
+            assert_eq!(trie.get(key)?, Some(&vec![i as u8]));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_hash_is_order_independent() -> Result<()> {
+        let mut trie1 = PatriciaMerkleTrie::new();
+        let mut trie2 = PatriciaMerkleTrie::new();
+        for (i, key) in keys().into_iter().enumerate() {
+            trie1.insert(key, vec![i as u8])?;
+        }
+        for (i, key) in keys().into_iter().enumerate().rev() {
+            trie2.insert(key, vec![i as u8])?;
+        }
+        assert_eq!(trie1.root_hash()?, trie2.root_hash()?);
+
+        // Deleting keys yields the same root as never inserting them
+        let mut trie3 = PatriciaMerkleTrie::new();
+        for (i, key) in keys().into_iter().enumerate().skip(3) {
+            trie3.insert(key, vec![i as u8])?;
+        }
+        for key in &keys()[..3] {
+            assert!(trie1.delete(key)?.is_some());
+        }
+        assert_eq!(trie1.root_hash()?, trie3.root_hash()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter_in_key_order() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for (i, key) in keys().into_iter().enumerate() {
+            trie.insert(key, vec![i as u8])?;
+        }
+        let mut expected = keys();
+        expected.sort();
+        let actual: Vec<_> = trie.iter().map(|(key, _)| key).collect();
+        assert_eq!(actual, expected);
+        Ok(())
+    }
+}
+
+mod secure_trie {
+    use patricia_merkle_trie::{Result, SecureTrie};
+
+    #[test]
+    fn test_many_keys() -> Result<()> {
+        let mut trie = SecureTrie::with_preimages();
+        for i in 0u32..64 {
+            trie.insert(i.to_be_bytes().to_vec(), vec![i as u8])?;
+        }
+        for i in 0u32..64 {
+            assert_eq!(trie.get(&i.to_be_bytes().to_vec())?, Some(&vec![i as u8]));
+        }
+        for (_, key, value) in trie.iter() {
+            let key = key.expect("preimage recorded");
+            assert_eq!(key[3], value[0]);
+        }
+        Ok(())
+    }
+}
+
+mod empty_key {
+    use super::*;
+
+    #[test]
+    fn test_namespace_default_record() -> Result<()> {
+        let mut with_default = PatriciaMerkleTrie::new();
+        with_default.insert(b"www".to_vec(), b"93.184.216.34".to_vec())?;
+        with_default.insert(b"mail".to_vec(), b"93.184.216.35".to_vec())?;
+        with_default.insert(vec![], b"93.184.216.1".to_vec())?;
+
+        assert_eq!(with_default.get(&vec![])?.unwrap(), b"93.184.216.1");
+        assert_eq!(
+            with_default.get(&b"www".to_vec())?.unwrap(),
+            b"93.184.216.34"
+        );
+        assert_eq!(with_default.iter().next().unwrap().0, Vec::<u8>::new());
+
+        // Removing the default record restores the original root
+        let mut without_default = PatriciaMerkleTrie::new();
+        without_default.insert(b"www".to_vec(), b"93.184.216.34".to_vec())?;
+        without_default.insert(b"mail".to_vec(), b"93.184.216.35".to_vec())?;
+        assert_ne!(with_default.root_hash()?, without_default.root_hash()?);
+
+        with_default.delete(&vec![])?;
+        assert_eq!(with_default.root_hash()?, without_default.root_hash()?);
+        Ok(())
+    }
+}
+
+mod merkle_proofs {
+    use super::*;
+    use patricia_merkle_trie::proof::{ExtendedProofTrait, MerkleProof, MerkleProofTrait};
+
+    type Trie = PatriciaMerkleTrie<Vec<u8>, Vec<u8>>;
+
+    #[test]
+    fn test_inclusion_and_exclusion() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(b"www.example.com".to_vec(), b"93.184.216.34".to_vec())?;
+        trie.insert(b"mail.example.com".to_vec(), b"93.184.216.35".to_vec())?;
+        trie.insert(b"ftp.example.com".to_vec(), b"93.184.216.36".to_vec())?;
+        let root = trie.root_hash()?;
+
+        let proof = trie.generate_proof(&b"mail.example.com".to_vec())?;
+        assert!(Trie::verify_proof(root.clone(), proof)?);
+
+        // "No such record" answers are verifiable too
+        let proof = trie.generate_exclusion_proof(&b"smtp.example.com".to_vec())?;
+        assert!(Trie::verify_exclusion_proof(root.clone(), proof.clone())?);
+
+        // The answer no longer holds once the record is added
+        trie.insert(b"smtp.example.com".to_vec(), b"93.184.216.37".to_vec())?;
+        assert!(!Trie::verify_exclusion_proof(trie.root_hash()?, proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_proof() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(b"www.example.com".to_vec(), b"93.184.216.34".to_vec())?;
+        trie.insert(b"mail.example.com".to_vec(), b"93.184.216.35".to_vec())?;
+        trie.insert(b"ftp.example.com".to_vec(), b"93.184.216.36".to_vec())?;
+        let root = trie.root_hash()?;
+
+        // One answer for several lookups, including a missing record
+        let keys = [
+            b"www.example.com".to_vec(),
+            b"ftp.example.com".to_vec(),
+            b"smtp.example.com".to_vec(),
+        ];
+        let proof = trie.generate_multi_proof(&keys)?;
+        assert_eq!(proof.values[0], Some(b"93.184.216.34".to_vec()));
+        assert_eq!(proof.values[2], None);
+        assert!(Trie::verify_multi_proof(root, proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_paginated_range_proofs() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..20u8 {
+            trie.insert(format!("user/{:02}", i).into_bytes(), vec![i; 40])?;
+        }
+        trie.insert(b"zone/1".to_vec(), b"other".to_vec())?;
+        let root = trie.root_hash()?;
+
+        // Walk the users in pages of five, each page proven complete
+        let mut listed = Vec::new();
+        for page in 0..4 {
+            let start = format!("user/{:02}", page * 5).into_bytes();
+            let end = format!("user/{:02}", page * 5 + 5).into_bytes();
+            let proof = trie.generate_range_proof(&start, &end)?;
+            assert_eq!(proof.entries.len(), 5);
+            listed.extend(proof.entries.iter().map(|(key, _)| key.clone()));
+            assert!(Trie::verify_range_proof(root.clone(), proof)?);
+        }
+        assert_eq!(listed.len(), 20);
+        assert!(listed.windows(2).all(|pair| pair[0] < pair[1]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_all_records_under_a_zone() -> Result<()> {
+        // Names are stored with their labels reversed, so a zone is a prefix
+        let mut trie = PatriciaMerkleTrie::new();
+        for (name, ip) in [
+            ("com.example.", "93.184.216.34"),
+            ("com.example.www.", "93.184.216.34"),
+            ("com.example.mail.", "93.184.216.35"),
+            ("com.examples.", "10.0.0.1"),
+            ("org.rust-lang.", "13.227.75.110"),
+        ] {
+            trie.insert(name.as_bytes().to_vec(), ip.as_bytes().to_vec())?;
+        }
+        let root = trie.root_hash()?;
+
+        let proof = trie.generate_prefix_proof(&b"com.example.".to_vec())?;
+        let names: Vec<_> = proof
+            .entries
+            .iter()
+            .map(|(key, _)| key.as_slice())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                &b"com.example."[..],
+                b"com.example.mail.",
+                b"com.example.www."
+            ]
+        );
+        assert!(Trie::verify_prefix_proof(root.clone(), proof.clone())?);
+
+        // A client cannot be told that a record under the zone is missing
+        let mut hidden = proof;
+        hidden.entries.remove(1);
+        assert!(!Trie::verify_prefix_proof(root, hidden)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_keys_that_prefix_other_keys() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(b"a".to_vec(), b"1".to_vec())?;
+        trie.insert(b"ab".to_vec(), b"2".to_vec())?;
+        trie.insert(b"abc".to_vec(), b"3".to_vec())?;
+        let root = trie.root_hash()?;
+
+        for key in [&b"a"[..], b"ab", b"abc"] {
+            let proof = trie.generate_proof(&key.to_vec())?;
+            assert!(Trie::verify_proof(root.clone(), proof)?);
+        }
+
+        // The proofs still hold once the longest key is gone
+        trie.delete(&b"abc".to_vec())?;
+        let root = trie.root_hash()?;
+        for key in [&b"a"[..], b"ab"] {
+            let proof = trie.generate_proof(&key.to_vec())?;
+            assert!(Trie::verify_proof(root.clone(), proof)?);
+        }
+        let proof = trie.generate_exclusion_proof(&b"abc".to_vec())?;
+        assert!(Trie::verify_exclusion_proof(root, proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_sent_as_bytes() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(b"www.example.com".to_vec(), b"93.184.216.34".to_vec())?;
+        trie.insert(b"mail.example.com".to_vec(), b"93.184.216.35".to_vec())?;
+        let root = trie.root_hash()?;
+
+        // The receiving side only has the bytes and the root
+        let bytes = trie
+            .generate_proof(&b"mail.example.com".to_vec())?
+            .to_bytes();
+        let proof = MerkleProof::from_bytes(&bytes)?;
+        assert_eq!(proof.value, b"93.184.216.35");
+        assert!(Trie::verify_proof(root, proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_step_of_foreign_proof() -> Result<()> {
+        use patricia_merkle_trie::verify::{check_proof, ProofError};
+
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(b"www.example.com".to_vec(), b"93.184.216.34".to_vec())?;
+        trie.insert(b"mail.example.com".to_vec(), b"93.184.216.35".to_vec())?;
+        let root = trie.root_hash()?;
+
+        // A proof from another implementation that encodes the root differently
+        let mut proof = trie.generate_proof(&b"mail.example.com".to_vec())?;
+        let last = proof.nodes[0].len() - 1;
+        proof.nodes[0][last] ^= 1;
+        let err = check_proof(&root, &proof).unwrap_err();
+        assert!(matches!(&err, ProofError::RootMismatch { expected, .. } if *expected == root));
+        assert!(err.to_string().starts_with("Root hash mismatch"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_of_proofs_against_one_root() -> Result<()> {
+        use patricia_merkle_trie::verify::BatchVerifier;
+
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..200u32 {
+            trie.insert(format!("record/{}", i).into_bytes(), vec![i as u8; 40])?;
+        }
+        let root = trie.root_hash()?;
+
+        // The gateway receives the proofs as bytes and checks them in one go
+        let mut verifier = BatchVerifier::new(root);
+        for i in 0..200u32 {
+            let bytes = trie
+                .generate_proof(&format!("record/{}", i).into_bytes())?
+                .to_bytes();
+            assert!(verifier.verify_proof(&MerkleProof::from_bytes(&bytes)?)?);
+        }
+        assert!(verifier.cached_nodes() < 200);
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_proof_serde_round_trip() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        trie.insert(b"www.example.com".to_vec(), b"93.184.216.34".to_vec())?;
+        trie.insert(b"mail.example.com".to_vec(), b"93.184.216.35".to_vec())?;
+
+        let proof = trie.generate_proof(&b"www.example.com".to_vec())?;
+        let json = serde_json::to_string(&proof).unwrap();
+        let decoded: MerkleProof = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(Trie::verify_proof(trie.root_hash()?, decoded)?);
+        Ok(())
+    }
+}
+
+mod ethereum_root {
+    use patricia_merkle_trie::{
+        eth::{keccak256, ordered_trie_root, EMPTY_ROOT},
+        eth_proof::EMPTY_CODE_HASH,
+        rlp::{encode_bytes, encode_list},
+        HashMode, PatriciaMerkleTrie, Result,
+    };
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn eth_root(entries: &[(&str, &str)]) -> Result<Vec<u8>> {
+        let mut trie = PatriciaMerkleTrie::with_mode(HashMode::Ethereum);
+        for (key, value) in entries {
+            trie.insert(key.as_bytes().to_vec(), value.as_bytes().to_vec())?;
+        }
+        trie.root_hash()
+    }
+
+    #[test]
+    fn test_empty_root() -> Result<()> {
+        assert_eq!(
+            eth_root(&[])?,
+            hex("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+        );
+        Ok(())
+    }
+
+    /// Bytes of a JSON-RPC quantity or data field
+    fn field(value: &serde_json::Value) -> Vec<u8> {
+        let s = value.as_str().unwrap().trim_start_matches("0x");
+        // Quantities are not padded to whole bytes
+        if s.len() % 2 == 1 {
+            hex(&format!("0{}", s))
+        } else {
+            hex(s)
+        }
+    }
+
+    /// RLP encoding of a quantity, big-endian without leading zeros
+    fn quantity(value: &serde_json::Value) -> Vec<u8> {
+        let bytes = field(value);
+        let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+        encode_bytes(&bytes[start..])
+    }
+
+    #[test]
+    fn test_mainnet_block_roots() -> Result<()> {
+        // Mainnet block 46147, which holds the first Ether transfer, with
+        // its transactions and receipts as returned by JSON-RPC
+        let path = format!(
+            "{}/tests/fixtures/mainnet_block_46147.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let block: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+        // Legacy transactions are the RLP list of their fields
+        let mut transactions = Vec::new();
+        for tx in block["transactions"].as_array().unwrap() {
+            let encoded = encode_list(&[
+                quantity(&tx["nonce"]),
+                quantity(&tx["gasPrice"]),
+                quantity(&tx["gas"]),
+                encode_bytes(&field(&tx["to"])),
+                quantity(&tx["value"]),
+                encode_bytes(&field(&tx["input"])),
+                quantity(&tx["v"]),
+                quantity(&tx["r"]),
+                quantity(&tx["s"]),
+            ]);
+            assert_eq!(keccak256(&encoded), field(&tx["hash"]));
+            transactions.push(encoded);
+        }
+
+        // Receipts before Byzantium start with the intermediate state root
+        let receipts = block["receipts"].as_array().unwrap().iter().map(|receipt| {
+            assert!(receipt["logs"].as_array().unwrap().is_empty());
+            encode_list(&[
+                encode_bytes(&field(&receipt["root"])),
+                quantity(&receipt["cumulativeGasUsed"]),
+                encode_bytes(&field(&receipt["logsBloom"])),
+                encode_list(&[]),
+            ])
+        });
+
+        assert_eq!(
+            ordered_trie_root(&transactions),
+            field(&block["transactionsRoot"])
+        );
+        assert_eq!(ordered_trie_root(receipts), field(&block["receiptsRoot"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_morden_genesis_state_root() -> Result<()> {
+        // Genesis allocation of the Morden test network, whose accounts
+        // start at nonce 2^20
+        let path = format!(
+            "{}/tests/fixtures/morden_genesis.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let genesis: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+        // Accounts are keyed by the hash of their address
+        let mut state = PatriciaMerkleTrie::with_mode(HashMode::Ethereum);
+        for (address, account) in genesis["alloc"].as_object().unwrap() {
+            let account = encode_list(&[
+                quantity(&account["nonce"]),
+                quantity(&account["balance"]),
+                encode_bytes(&EMPTY_ROOT),
+                encode_bytes(&EMPTY_CODE_HASH),
+            ]);
+            state.insert(keccak256(&hex(&address[2..])), account)?;
+        }
+        assert_eq!(state.root_hash()?, field(&genesis["stateRoot"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_vectors() -> Result<()> {
+        let vectors: &[(&[(&str, &str)], &str)] = &[
+            (
+                &[
+                    ("doe", "reindeer"),
+                    ("dog", "puppy"),
+                    ("dogglesworth", "cat"),
+                ],
+                "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3",
+            ),
+            (
+                &[("foo", "bar"), ("food", "bass")],
+                "17beaa1648bafa633cda809c90c04af50fc8aed3cb40d16efbddee6fdf63c4c3",
+            ),
+            (
+                &[("be", "e"), ("dog", "puppy"), ("bed", "d")],
+                "3f67c7a47520f79faa29255d2d3c084a7a6df0453116ed7232ff10277a8be68b",
+            ),
+            (
+                &[("test", "test"), ("te", "testy")],
+                "8452568af70d8d140f58d941338542f645fcca50094b20f3c3d8c3df49337928",
+            ),
+            (
+                &[
+                    ("do", "verb"),
+                    ("horse", "stallion"),
+                    ("doge", "coin"),
+                    ("dog", "puppy"),
+                ],
+                "5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84",
+            ),
+        ];
+
+        for (entries, root) in vectors {
+            assert_eq!(eth_root(entries)?, hex(root), "entries: {:?}", entries);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_independent_of_order() -> Result<()> {
+        let forward = eth_root(&[("do", "verb"), ("dog", "puppy"), ("doge", "coin")])?;
+        let reverse = eth_root(&[("doge", "coin"), ("dog", "puppy"), ("do", "verb")])?;
+        assert_eq!(forward, reverse);
+        Ok(())
+    }
+
+    #[test]
+    fn test_native_mode_unchanged() -> Result<()> {
+        let mut native = PatriciaMerkleTrie::new();
+        let mut ethereum = PatriciaMerkleTrie::with_mode(HashMode::Ethereum);
+        native.insert(b"key".to_vec(), b"value".to_vec())?;
+        ethereum.insert(b"key".to_vec(), b"value".to_vec())?;
+        assert_eq!(native.mode(), HashMode::Native);
+        assert_ne!(native.root_hash()?, ethereum.root_hash()?);
+        Ok(())
+    }
+}
+
+mod eth_get_proof {
+    use patricia_merkle_trie::{
+        eth_proof::{EthProof, StorageProof},
+        Result,
+    };
+    use serde_json::Value;
+
+    fn hex(value: &Value) -> Vec<u8> {
+        let s = value.as_str().unwrap().trim_start_matches("0x");
+        // Quantities are not padded to whole bytes
+        let s = if s.len() % 2 == 1 {
+            format!("0{}", s)
+        } else {
+            s.to_string()
+        };
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn fixed<const N: usize>(value: &Value) -> [u8; N] {
+        let bytes = hex(value);
+        let mut out = [0u8; N];
+        out[N - bytes.len()..].copy_from_slice(&bytes);
+        out
+    }
+
+    fn nodes(value: &Value) -> Vec<Vec<u8>> {
+        value.as_array().unwrap().iter().map(hex).collect()
+    }
+
+    fn load(name: &str) -> (Vec<u8>, EthProof) {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let fixture: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let result = &fixture["result"];
+        let proof = EthProof {
+            address: fixed(&result["address"]),
+            account_proof: nodes(&result["accountProof"]),
+            balance: fixed(&result["balance"]),
+            code_hash: fixed(&result["codeHash"]),
+            nonce: u64::from_be_bytes(fixed(&result["nonce"])),
+            storage_hash: fixed(&result["storageHash"]),
+            storage_proof: result["storageProof"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|slot| StorageProof {
+                    key: fixed(&slot["key"]),
+                    value: fixed(&slot["value"]),
+                    proof: nodes(&slot["proof"]),
+                })
+                .collect(),
+        };
+        (hex(&fixture["stateRoot"]), proof)
+    }
+
+    #[test]
+    fn test_fixtures_verify() -> Result<()> {
+        for name in [
+            "eth_get_proof_account.json",
+            "eth_get_proof_contract.json",
+            "eth_get_proof_missing_account.json",
+            "eth_get_proof_wide_balance.json",
+        ] {
+            let (state_root, proof) = load(name);
+            assert!(proof.verify(&state_root)?, "fixture: {}", name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_balance_wider_than_u128() -> Result<()> {
+        let (state_root, proof) = load("eth_get_proof_wide_balance.json");
+        assert!(proof.balance[..16].iter().any(|&b| b != 0));
+
+        let mut wrong_balance = proof.clone();
+        wrong_balance.balance[6] = 0;
+        assert!(!wrong_balance.verify(&state_root)?);
+        assert!(proof.verify(&state_root)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampered_fixture_rejected() -> Result<()> {
+        let (state_root, proof) = load("eth_get_proof_contract.json");
+
+        let mut wrong_nonce = proof.clone();
+        wrong_nonce.nonce += 1;
+        assert!(!wrong_nonce.verify(&state_root)?);
+
+        let mut wrong_slot = proof.clone();
+        wrong_slot.storage_proof[1].value[31] ^= 1;
+        assert!(!wrong_slot.verify(&state_root)?);
+
+        let mut wrong_node = proof.clone();
+        wrong_node.account_proof[1][40] ^= 1;
+        assert!(wrong_node.verify(&state_root).is_err());
+
+        let mut wrong_root = state_root;
+        wrong_root[0] ^= 1;
+        assert!(proof.verify(&wrong_root).is_err());
+        Ok(())
+    }
+}
+
+mod state_witness {
+    use patricia_merkle_trie::witness::{apply_witness, verify_witness, Operation};
+    use patricia_merkle_trie::{PatriciaMerkleTrie, Result};
+
+    #[test]
+    fn test_light_client_checks_transition() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..50u32 {
+            trie.insert(format!("account/{}", i).into_bytes(), vec![1; 40])?;
+        }
+        let old_root = trie.root_hash()?;
+
+        let operations = vec![
+            Operation::Insert(b"account/7".to_vec(), vec![2; 40]),
+            Operation::Delete(b"account/13".to_vec()),
+            Operation::Insert(b"account/50".to_vec(), vec![3; 40]),
+        ];
+        let witness = trie.generate_witness(operations)?;
+        trie.insert(b"account/7".to_vec(), vec![2; 40])?;
+        trie.delete(&b"account/13".to_vec())?;
+        trie.insert(b"account/50".to_vec(), vec![3; 40])?;
+        let new_root = trie.root_hash()?;
+
+        // The client only holds the old root and the witness
+        assert_eq!(apply_witness(&old_root, &witness)?, new_root);
+        assert!(verify_witness(&old_root, &new_root, &witness)?);
+        assert!(!verify_witness(&old_root, &old_root, &witness)?);
+        Ok(())
+    }
+}
+
+mod partial_trie {
+    use patricia_merkle_trie::proof::MerkleProofTrait;
+    use patricia_merkle_trie::{PartialTrie, PatriciaMerkleTrie, Result, TrieError};
+
+    #[test]
+    fn test_stateless_updates_from_proofs() -> Result<()> {
+        let mut trie = PatriciaMerkleTrie::new();
+        for i in 0..50u32 {
+            trie.insert(format!("account/{}", i).into_bytes(), vec![i as u8; 40])?;
+        }
+
+        // A stateless client receives proofs for the accounts it touches
+        let mut partial = PartialTrie::new(trie.root_hash()?);
+        for key in [&b"account/7"[..], b"account/42"] {
+            partial.reveal(&trie.generate_proof(&key.to_vec())?.nodes)?;
+        }
+        assert_eq!(partial.get(b"account/7")?, Some(&[7; 40][..]));
+        assert!(matches!(
+            partial.get(b"account/13"),
+            Err(TrieError::MissingWitness(_))
+        ));
+
+        partial.insert(b"account/7", vec![2; 40])?;
+        partial.insert(b"account/42", vec![3; 40])?;
+        trie.insert(b"account/7".to_vec(), vec![2; 40])?;
+        trie.insert(b"account/42".to_vec(), vec![3; 40])?;
+        assert_eq!(partial.root_hash()?, trie.root_hash()?);
+        Ok(())
+    }
+}